@@ -112,6 +112,17 @@ impl Aabb{
         true
     }
 
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().max(0.0);
+        let dy = self.y.size().max(0.0);
+        let dz = self.z.size().max(0.0);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub fn centroid(&self) -> Vector3<f64> {
+        Vector3::new(0.5 * (self.x.min + self.x.max), 0.5 * (self.y.min + self.y.max), 0.5 * (self.z.min + self.z.max))
+    }
+
     pub fn longest_axis(&self) -> i64 {
         if self.x.size() > self.y.size() {
             if self.x.size() > self.z.size() {0} else {2}
@@ -0,0 +1,120 @@
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::ray::Ray;
+use crate::texture::Texture;
+
+const BUMP_DELTA:f64 = 0.0005;
+
+pub enum Perturbation {
+    NormalMap { map:Arc<dyn Texture>, strength:f64 },
+    Bump { height:Arc<dyn Texture>, scale:f64 },
+}
+
+pub struct ShadingNormal {
+    material:Arc<dyn Material>,
+    perturbation:Perturbation,
+}
+
+impl ShadingNormal {
+    pub fn normal_map(material:Arc<dyn Material>, map:Arc<dyn Texture>, strength:f64) -> Self {
+        ShadingNormal {
+            material,
+            perturbation:Perturbation::NormalMap { map, strength },
+        }
+    }
+
+    pub fn bump(material:Arc<dyn Material>, height:Arc<dyn Texture>, scale:f64) -> Self {
+        ShadingNormal {
+            material,
+            perturbation:Perturbation::Bump { height, scale },
+        }
+    }
+
+    fn height(tex:&Arc<dyn Texture>, u:f64, v:f64, p:&Vector3<f64>) -> f64 {
+        let h = tex.value(u, v, p);
+        (h.x + h.y + h.z) / 3.0
+    }
+
+    fn perturbed_normal(&self, rec:&HitRecord, outward:&Vector3<f64>) -> Option<Vector3<f64>> {
+        match &self.perturbation {
+            Perturbation::NormalMap { map, strength } => {
+                let tangent = rec.dpdu - outward * outward.dot(&rec.dpdu);
+                if tangent.norm_squared() < 1e-16 {
+                    return None;
+                }
+                let tangent = tangent.normalize();
+                let mut bitangent = outward.cross(&tangent);
+                if bitangent.dot(&rec.dpdv) < 0.0 {
+                    bitangent = -bitangent;
+                }
+                let c = map.value(rec.u, rec.v, &rec.p) * 2.0 - Vector3::new(1.0,1.0,1.0);
+                let local = Vector3::new(c.x * strength, c.y * strength, c.z.max(0.0));
+                let normal = local.x * tangent + local.y * bitangent + local.z * outward;
+                if normal.norm_squared() == 0.0 {None} else {Some(normal.normalize())}
+            }
+            Perturbation::Bump { height, scale } => {
+                let d = Self::height(height, rec.u, rec.v, &rec.p);
+                let du = Self::height(height, rec.u + BUMP_DELTA, rec.v, &(rec.p + BUMP_DELTA * rec.dpdu));
+                let dv = Self::height(height, rec.u, rec.v + BUMP_DELTA, &(rec.p + BUMP_DELTA * rec.dpdv));
+                let dpdu = rec.dpdu + scale * (du - d) / BUMP_DELTA * outward;
+                let dpdv = rec.dpdv + scale * (dv - d) / BUMP_DELTA * outward;
+                let normal = dpdu.cross(&dpdv);
+                if normal.norm_squared() == 0.0 {
+                    return None;
+                }
+                let normal = normal.normalize();
+                Some(if normal.dot(outward) < 0.0 {-normal} else {normal})
+            }
+        }
+    }
+
+    fn shade(&self, rec:&HitRecord) -> HitRecord {
+        let mut shaded = rec.clone();
+        let outward = if rec.front_face {rec.normal} else {-rec.normal};
+        if let Some(normal) = self.perturbed_normal(rec, &outward) {
+            shaded.normal = if rec.front_face {normal} else {-normal};
+        }
+        shaded
+    }
+}
+
+impl Material for ShadingNormal {
+    fn scatter(&self, r_in:&Ray, rec:&HitRecord, srec:&mut ScatterRecord) -> bool {
+        self.material.scatter(r_in, &self.shade(rec), srec)
+    }
+
+    fn emitted(&self, r_in:&Ray, rec:&HitRecord, u:f64, v:f64, p:&Vector3<f64>) -> Vector3<f64> {
+        self.material.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in:&Ray, rec:&HitRecord, scattered:&Ray) -> f64 {
+        self.material.scattering_pdf(r_in, &self.shade(rec), scattered)
+    }
+
+    fn scattering_value(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, scattered:&Ray) -> Vector3<f64> {
+        self.material.scattering_value(r_in, &self.shade(rec), srec, scattered)
+    }
+
+    fn absorption(&self, rec:&HitRecord) -> Vector3<f64> {
+        self.material.absorption(rec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_two_sided(&self) -> bool {
+        self.material.is_two_sided()
+    }
+
+    fn radiant_exitance(&self, rec:&HitRecord) -> Vector3<f64> {
+        self.material.radiant_exitance(rec)
+    }
+}
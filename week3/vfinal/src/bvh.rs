@@ -1,6 +1,5 @@
 use nalgebra::Vector3;
 use std::sync::Arc;
-use std::cmp::Ordering;
 
 use crate::hittable::{Hittable, HitRecord};
 use crate::aabb::Aabb;
@@ -8,79 +7,444 @@ use crate::hittable_list::HittableList;
 use crate::ray::Ray;
 use crate::interval::Interval;
 use crate::rtweekend::random_int;
+use std::thread;
 
-pub struct BvhNode {
-    left:Arc<dyn Hittable>,
-    right:Arc<dyn Hittable>,
+const STACK_SIZE:usize = 64;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    Sah,
+    Middle,
+}
+
+#[derive(Clone)]
+struct PrimInfo {
+    index:usize,
     bbox:Aabb,
+    centroid:Vector3<f64>,
 }
 
-impl BvhNode {
-    pub fn initial (list:&mut HittableList) -> Self {
-        let len = list.objects.len() as i64;
-        Self::new(&mut list.objects, 0, len)
+pub enum BuildNode {
+    Leaf { bbox:Aabb, start:usize, count:usize },
+    Interior { bbox:Aabb, axis:usize, left:Box<BuildNode>, right:Box<BuildNode> },
+}
+
+impl BuildNode {
+    pub fn bbox(&self) -> &Aabb {
+        match self {
+            BuildNode::Leaf { bbox, .. } => bbox,
+            BuildNode::Interior { bbox, .. } => bbox,
+        }
     }
 
-    pub fn new (objects:&mut Vec<Arc<dyn Hittable>>, start:i64, end:i64) -> Self {
-        let mut bvh = BvhNode {
-            left: objects[start as usize].clone(),
-            right: objects[start as usize].clone(),
-            bbox: Aabb::empty(),
+    pub fn node_count(&self) -> usize {
+        match self {
+            BuildNode::Leaf { .. } => 1,
+            BuildNode::Interior { left, right, .. } => 1 + left.node_count() + right.node_count(),
+        }
+    }
+}
+
+pub struct BvhBuilder {
+    pub split_method:SplitMethod,
+    pub max_leaf_size:usize,
+    pub bins:usize,
+    pub traversal_cost:f64,
+    pub parallel_threshold:usize,
+}
+
+impl BvhBuilder {
+    pub fn new() -> Self {
+        BvhBuilder {
+            split_method:SplitMethod::Sah,
+            max_leaf_size:4,
+            bins:12,
+            traversal_cost:0.125,
+            parallel_threshold:4096,
+        }
+    }
+
+    pub fn build_tree(&self, objects:&[Arc<dyn Hittable>]) -> (BuildNode, Vec<Arc<dyn Hittable>>) {
+        let mut prims:Vec<PrimInfo> = objects.iter().enumerate().map(|(index, object)| {
+            let bbox = object.bounding_box();
+            let centroid = bbox.centroid();
+            PrimInfo { index, bbox, centroid }
+        }).collect();
+        let root = self.build_recursive(&mut prims, 0);
+        let ordered = prims.iter().map(|p| objects[p.index].clone()).collect();
+        (root, ordered)
+    }
+
+    fn build_recursive(&self, prims:&mut [PrimInfo], offset:usize) -> BuildNode {
+        let mut bbox = Aabb::empty();
+        let mut cmin = Vector3::repeat(f64::INFINITY);
+        let mut cmax = Vector3::repeat(f64::NEG_INFINITY);
+        for p in prims.iter() {
+            bbox = Aabb::merge(&bbox, &p.bbox);
+            cmin = cmin.inf(&p.centroid);
+            cmax = cmax.sup(&p.centroid);
+        }
+        let n = prims.len();
+        if n <= 1 {
+            return BuildNode::Leaf { bbox, start:offset, count:n };
+        }
+
+        let extent = cmax - cmin;
+        let axis = extent.imax();
+        if extent[axis] <= 0.0 {
+            if n <= self.max_leaf_size {
+                return BuildNode::Leaf { bbox, start:offset, count:n };
+            }
+            return self.split_at(prims, offset, bbox, axis, n / 2);
+        }
+
+        let (axis, mid) = match self.split_method {
+            SplitMethod::Sah => match self.sah_partition(prims, &bbox, &cmin, &cmax) {
+                Some(split) => split,
+                None => return BuildNode::Leaf { bbox, start:offset, count:n },
+            },
+            SplitMethod::Middle => {
+                if n <= self.max_leaf_size {
+                    return BuildNode::Leaf { bbox, start:offset, count:n };
+                }
+                (axis, 0)
+            }
         };
-        for object_index in start..end {
-            bvh.bbox = Aabb::merge(&bvh.bbox, &objects[object_index as usize].bounding_box());
-        }
-        let axis = bvh.bbox.longest_axis();
-        let object_span = end - start;
-        if object_span == 1 {}
-        else if object_span == 2 {
-            bvh.right = objects[start as usize + 1].clone();
-        }
-        else {
-            let mut to_sort:Vec<Arc<dyn Hittable>> = Vec::new();
-            for i in start..end {
-                to_sort.push(objects[i as usize].clone());
+        self.split_at(prims, offset, bbox, axis, mid)
+    }
+
+    fn split_at(&self, prims:&mut [PrimInfo], offset:usize, bbox:Aabb, axis:usize, mid:usize) -> BuildNode {
+        let n = prims.len();
+        let mid = if mid == 0 || mid >= n {
+            prims.select_nth_unstable_by(n / 2, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            n / 2
+        } else {
+            mid
+        };
+        let (left_prims, right_prims) = prims.split_at_mut(mid);
+        let (left, right) = if n > self.parallel_threshold {
+            thread::scope(|s| {
+                let left = s.spawn(|| self.build_recursive(left_prims, offset));
+                let right = self.build_recursive(right_prims, offset + mid);
+                (left.join().unwrap(), right)
+            })
+        } else {
+            (self.build_recursive(left_prims, offset), self.build_recursive(right_prims, offset + mid))
+        };
+        BuildNode::Interior { bbox, axis, left:Box::new(left), right:Box::new(right) }
+    }
+
+    fn sah_partition(&self, prims:&mut [PrimInfo], bbox:&Aabb, cmin:&Vector3<f64>, cmax:&Vector3<f64>) -> Option<(usize, usize)> {
+        let n = prims.len();
+        let bins = self.bins.max(2);
+        let total_area = bbox.surface_area();
+        let bin_of = |c:f64, axis:usize| -> usize {
+            let t = (c - cmin[axis]) / (cmax[axis] - cmin[axis]);
+            ((t * bins as f64) as usize).min(bins - 1)
+        };
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_axis = 0;
+        let mut best_bin = 0;
+        for axis in 0..3 {
+            if cmax[axis] <= cmin[axis] {
+                continue;
+            }
+            let mut counts = vec![0usize; bins];
+            let mut bounds = vec![Aabb::empty(); bins];
+            for p in prims.iter() {
+                let b = bin_of(p.centroid[axis], axis);
+                counts[b] += 1;
+                bounds[b] = Aabb::merge(&bounds[b], &p.bbox);
+            }
+
+            let mut right_area = vec![0.0; bins];
+            let mut right_count = vec![0usize; bins];
+            let mut acc_box = Aabb::empty();
+            let mut acc_count = 0;
+            for i in (1..bins).rev() {
+                acc_box = Aabb::merge(&acc_box, &bounds[i]);
+                acc_count += counts[i];
+                right_area[i] = acc_box.surface_area();
+                right_count[i] = acc_count;
+            }
+
+            let mut acc_box = Aabb::empty();
+            let mut acc_count = 0;
+            for i in 0..bins - 1 {
+                acc_box = Aabb::merge(&acc_box, &bounds[i]);
+                acc_count += counts[i];
+                if acc_count == 0 || right_count[i + 1] == 0 {
+                    continue;
+                }
+                let cost = self.traversal_cost
+                    + (acc_count as f64 * acc_box.surface_area() + right_count[i + 1] as f64 * right_area[i + 1]) / total_area;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_axis = axis;
+                    best_bin = i;
+                }
+            }
+        }
+
+        if n <= self.max_leaf_size && best_cost >= n as f64 {
+            return None;
+        }
+        if best_cost == f64::INFINITY {
+            return Some(((cmax - cmin).imax(), 0));
+        }
+
+        let mut mid = 0;
+        for i in 0..n {
+            if bin_of(prims[i].centroid[best_axis], best_axis) <= best_bin {
+                prims.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some((best_axis, mid))
+    }
+}
+
+impl Default for BvhBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LinearNode {
+    min:[f64;3],
+    max:[f64;3],
+    offset:usize,
+    count:usize,
+    axis:usize,
+}
+
+pub struct LinearBvh {
+    nodes:Vec<LinearNode>,
+    primitives:Vec<Arc<dyn Hittable>>,
+    bbox:Aabb,
+}
+
+impl LinearBvh {
+    pub fn initial (list:&mut HittableList) -> Self {
+        Self::new(&BvhBuilder::new(), &list.objects)
+    }
+
+    pub fn new (builder:&BvhBuilder, objects:&[Arc<dyn Hittable>]) -> Self {
+        if objects.is_empty() {
+            return LinearBvh {
+                nodes:Vec::new(),
+                primitives:Vec::new(),
+                bbox:Aabb::empty(),
+            };
+        }
+        let (root, primitives) = builder.build_tree(objects);
+        let mut nodes = Vec::with_capacity(root.node_count());
+        Self::flatten(&root, &mut nodes);
+        LinearBvh {
+            nodes,
+            primitives,
+            bbox:root.bbox().clone(),
+        }
+    }
+
+    fn flatten(node:&BuildNode, nodes:&mut Vec<LinearNode>) -> usize {
+        let bbox = node.bbox();
+        let index = nodes.len();
+        nodes.push(LinearNode {
+            min:[bbox.x.min, bbox.y.min, bbox.z.min],
+            max:[bbox.x.max, bbox.y.max, bbox.z.max],
+            offset:0,
+            count:0,
+            axis:0,
+        });
+        match node {
+            BuildNode::Leaf { start, count, .. } => {
+                nodes[index].offset = *start;
+                nodes[index].count = *count;
             }
-            to_sort.sort_by(|a, b| Self::box_compare(&a, &b, axis) );
-            for i in start..end {
-                objects[i as usize] = to_sort[i as usize - start as usize].clone();
+            BuildNode::Interior { axis, left, right, .. } => {
+                Self::flatten(left, nodes);
+                let second = Self::flatten(right, nodes);
+                nodes[index].offset = second;
+                nodes[index].axis = *axis;
             }
-            let mid = start + object_span / 2;
-            bvh.left = Arc::new(BvhNode::new(objects, start, mid));
-            bvh.right = Arc::new(BvhNode::new(objects, mid, end));
         }
-        bvh.bbox = Aabb::merge(&bvh.left.bounding_box(),&bvh.right.bounding_box());
-        bvh
+        index
     }
 
-    pub fn box_compare (a:&Arc<dyn Hittable>, b:&Arc<dyn Hittable>, axis_index:i64) -> Ordering {
-        let a_axis_interval = a.bounding_box().axis_interval(axis_index);
-        let b_axis_interval = b.bounding_box().axis_interval(axis_index);
-        if a_axis_interval.min < b_axis_interval.min {Ordering::Less} 
-        else if a_axis_interval.min > b_axis_interval.min {Ordering::Greater}
-        else {Ordering::Equal}
+    fn hit_node(node:&LinearNode, orig:&[f64;3], inv_dir:&[f64;3], t_min:f64, t_max:f64) -> bool {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let near = (node.min[axis] - orig[axis]) * inv_dir[axis];
+            let far = (node.max[axis] - orig[axis]) * inv_dir[axis];
+            let (near, far) = if near > far { (far, near) } else { (near, far) };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
     }
 }
 
-impl Hittable for BvhNode {
-    fn hit (&self, r: &Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
-        if !self.bbox.hit(&r, &ray_t) {
+impl Hittable for LinearBvh {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
             return false;
         }
-        let hit_left = self.left.hit(&r, &ray_t, rec);
-        let hit_right = self.right.hit(&r, &Interval::new(ray_t.min, if hit_left {rec.t} else {ray_t.max}), rec);
-        hit_left || hit_right
+        let orig = [r.orig.x, r.orig.y, r.orig.z];
+        let inv_dir = [1.0 / r.dir.x, 1.0 / r.dir.y, 1.0 / r.dir.z];
+        let dir_is_neg = [inv_dir[0] < 0.0, inv_dir[1] < 0.0, inv_dir[2] < 0.0];
+
+        let mut temp_rec = HitRecord::new();
+        let mut closest_so_far = ray_t.max;
+        let mut hit_anything = false;
+        let mut stack = [0usize;STACK_SIZE];
+        let mut stack_len = 0;
+        let mut overflow:Vec<usize> = Vec::new();
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if Self::hit_node(node, &orig, &inv_dir, ray_t.min, closest_so_far) {
+                if node.count > 0 {
+                    for object in self.primitives[node.offset..node.offset + node.count].iter() {
+                        if object.hit(r, &Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+                            hit_anything = true;
+                            closest_so_far = temp_rec.t;
+                            *rec = temp_rec.clone();
+                        }
+                    }
+                }
+                else {
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    if stack_len < STACK_SIZE {
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                    } else {
+                        overflow.push(far);
+                    }
+                    current = near;
+                    continue;
+                }
+            }
+            if let Some(next) = overflow.pop() {
+                current = next;
+            } else if stack_len > 0 {
+                stack_len -= 1;
+                current = stack[stack_len];
+            } else {
+                break;
+            }
+        }
+        hit_anything
     }
 
     fn bounding_box(&self) -> Aabb {
         self.bbox.clone()
     }
-    
+
     fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
-        0.0
+        if self.primitives.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.primitives.len() as f64;
+        self.primitives.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
     }
-    
+
     fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
-        Vector3::new(1.0,0.0,0.0)
+        if self.primitives.is_empty() {
+            return Vector3::new(1.0,0.0,0.0);
+        }
+        let int_size = self.primitives.len() as i64;
+        self.primitives[random_int(0, int_size - 1) as usize].random(origin)
     }
-}
\ No newline at end of file
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        for object in self.primitives.iter() {
+            object.clone().collect_emitters(emitters);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    fn sphere(center:Vector3<f64>, radius:f64) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(center, radius, Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn trace(world:&dyn Hittable, r:&Ray) -> Option<(f64, Vector3<f64>)> {
+        let mut rec = HitRecord::new();
+        if world.hit(r, &Interval::new(0.001, f64::INFINITY), &mut rec) {
+            Some((rec.t, rec.p))
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn linear_bvh_matches_brute_force() {
+        let mut list = HittableList::new();
+        for i in 0..200 {
+            let f = i as f64;
+            let center = Vector3::new((f * 7.3) % 20.0 - 10.0, (f * 3.1) % 20.0 - 10.0, (f * 5.7) % 20.0 - 10.0);
+            list.add(sphere(center, 0.3 + (f * 0.37) % 0.7));
+        }
+        let linear = LinearBvh::initial(&mut list.clone());
+        let middle = LinearBvh::new(&BvhBuilder { split_method:SplitMethod::Middle, ..BvhBuilder::new() }, &list.objects);
+
+        let mut hits = 0;
+        for i in 0..2000 {
+            let f = i as f64;
+            let origin = Vector3::new(-30.0, (f * 0.61) % 24.0 - 12.0, (f * 0.37) % 24.0 - 12.0);
+            let target = Vector3::new(10.0, (f * 0.29) % 24.0 - 12.0, (f * 0.83) % 24.0 - 12.0);
+            let r = Ray::new(origin, target - origin);
+            let expected = trace(&list, &r);
+            assert_eq!(trace(&linear, &r), expected);
+            assert_eq!(trace(&middle, &r), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn linear_bvh_forwards_light_sampling() {
+        let mut list = HittableList::new();
+        for i in 0..6 {
+            list.add(sphere(Vector3::new(4.0 * i as f64 - 10.0, 5.0, 0.0), 1.0));
+        }
+        let linear = LinearBvh::initial(&mut list.clone());
+        let origin = Vector3::zeros();
+        for i in 0..6 {
+            let direction = Vector3::new(4.0 * i as f64 - 10.0, 5.0, 0.0);
+            let expected = list.pdf_value(&origin, &direction);
+            assert!(expected > 0.0);
+            assert!((linear.pdf_value(&origin, &direction) - expected).abs() < 1e-12);
+        }
+        for _ in 0..100 {
+            assert!(linear.pdf_value(&origin, &linear.random(&origin)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn linear_bvh_traverses_degenerate_deep_trees() {
+        let mut list = HittableList::new();
+        for i in 0..100 {
+            list.add(sphere(Vector3::new(16f64.powi(i), 0.0, 0.0), 0.25));
+        }
+        let linear = LinearBvh::initial(&mut list);
+        let r = Ray::new(Vector3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(trace(&linear, &r).map(|(t, _)| t), Some(10.75));
+    }
+}
@@ -1,17 +1,31 @@
 use nalgebra::Vector3;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::sync::{Arc, Mutex, RwLock};
+use image::ImageResult;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 use std::thread;
 
-use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
+use crate::pdf::{power_heuristic, HittablePdf, Pdf};
 use crate::ray::Ray;
-use crate::hittable_list::HittableList;
 use crate::hittable::{HitRecord,Hittable};
 use crate::interval::Interval;
-use crate::color::write_color;
-use crate::rtweekend::{random_double, random_f64};
-use crate::vec3::{random_on_hemisphere, random_unit_vector, random_in_unit_disk};
-use crate::material::{Material, ScatterRecord};
+use crate::framebuffer::Framebuffer;
+use crate::image_writer::save_image;
+use crate::tile::Tile;
+use crate::rtweekend::{random_double, seed_sample};
+use crate::vec3::random_in_unit_disk;
+use crate::material::ScatterRecord;
+use crate::environment::Environment;
+use crate::light::Light;
+use crate::spectrum::{rgb_to_spectrum, sample_wavelength, spectrum_to_rgb, wavelengths};
+
+// What direct lighting needs to know about the path besides the hit: the
+// absorption of the medium it is in and its sampled wavelengths.
+pub struct PathState {
+    pub absorption:Vector3<f64>,
+    pub lambdas:Vector3<f64>,
+}
 
 #[derive(Clone)]
 pub struct Camera {
@@ -26,6 +40,14 @@ pub struct Camera {
     pub vup:Vector3<f64>,
     pub defocus_angle:f64,
     pub focus_dist:f64,
+    pub output_file:String,
+    pub bit_depth:i64,
+    pub threads:i64,
+    pub tile_size:i64,
+    pub seed:u64,
+    pub spectral:bool,
+    pub environment:Option<Arc<dyn Environment>>,
+    pub delta_lights:Vec<Arc<dyn Light>>,
 
     pub image_height:i64,
     pub pixel_samples_scale:f64,
@@ -56,6 +78,14 @@ impl Camera {
             vup:Vector3::new(0.0,1.0,0.0),
             defocus_angle:0.0,
             focus_dist:10.0,
+            output_file:String::from("image.png"),
+            bit_depth:8,
+            threads:thread::available_parallelism().map(|n| n.get() as i64).unwrap_or(1),
+            tile_size:16,
+            seed:0,
+            spectral:false,
+            environment:None,
+            delta_lights:Vec::new(),
 
             image_height:100,
             pixel_samples_scale:0.0,
@@ -73,88 +103,227 @@ impl Camera {
         }
     }
     pub fn ray_color (&self, r:&Ray, depth:i64, world:&Arc<dyn Hittable>, lights:&Arc<dyn Hittable>) -> Vector3<f64> {
-        if depth <= 0 {
-            return Vector3::new(0.0,0.0,0.0);
+        let mut radiance = Vector3::new(0.0,0.0,0.0);
+        let mut throughput = Vector3::new(1.0,1.0,1.0);
+        let mut ray = r.clone();
+        let mut specular_bounce = true;
+        let mut prev_p = Vector3::zeros();
+        let mut prev_pdf = 0.0;
+        let lambdas = wavelengths(r.wavelength);
+        let mut secondary_terminated = false;
+        let mut media:Vec<Vector3<f64>> = Vec::new();
+
+        for _ in 0..depth {
+            let mut rec = HitRecord::new();
+            if !world.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut rec) {
+                match &self.environment {
+                    Some(environment) => {
+                        let weight = if specular_bounce {
+                            1.0
+                        } else {
+                            power_heuristic(1, prev_pdf, 1, lights.pdf_value(&prev_p, &ray.direction()))
+                        };
+                        let color_from_environment = environment.radiance(&ray.direction());
+                        radiance += weight * throughput.component_mul(&self.sampled(color_from_environment, &lambdas));
+                    }
+                    None => radiance += throughput.component_mul(&self.sampled(self.background, &lambdas)),
+                }
+                break;
+            }
+
+            let absorption = media.last().copied().unwrap_or_else(Vector3::zeros);
+            throughput = throughput.component_mul(&transmittance(&absorption, rec.t * ray.direction().norm()));
+
+            let color_from_emission = rec.mat.emitted(&ray, &rec, rec.u, rec.v, &rec.p);
+            if color_from_emission != Vector3::zeros() {
+                let weight = if specular_bounce {
+                    1.0
+                } else {
+                    power_heuristic(1, prev_pdf, 1, lights.pdf_value(&prev_p, &ray.direction()))
+                };
+                radiance += weight * throughput.component_mul(&self.sampled(color_from_emission, &lambdas));
+            }
+
+            let mut srec = ScatterRecord::new();
+            if !rec.mat.scatter(&ray, &rec, &mut srec) {
+                break;
+            }
+
+            if self.spectral && !secondary_terminated && rec.mat.is_dispersive() {
+                throughput = Vector3::new(3.0 * throughput.x, 0.0, 0.0);
+                secondary_terminated = true;
+            }
+
+            if srec.skip_pdf {
+                throughput = throughput.component_mul(&self.sampled(srec.attenuation, &lambdas));
+                self.cross_interface(&mut media, &rec, &srec.skip_pdf_ray.direction(), &lambdas);
+                ray = srec.skip_pdf_ray.clone().with_wavelength(ray.wavelength);
+                specular_bounce = true;
+                continue;
+            }
+
+            let state = PathState { absorption, lambdas };
+            radiance += throughput.component_mul(&self.sample_direct(&ray, &rec, &srec, world, lights, &state));
+            radiance += throughput.component_mul(&self.sample_delta_lights(&ray, &rec, &srec, world, &state));
+
+            let scattered = Ray::initial(rec.p, srec.pdf_ptr.generate(), ray.time()).with_wavelength(ray.wavelength);
+            let pdf_val = srec.pdf_ptr.value(&scattered.direction());
+            if pdf_val <= 0.0 {
+                break;
+            }
+            let scattering_value = rec.mat.scattering_value(&ray, &rec, &srec, &scattered);
+            throughput = throughput.component_mul(&self.sampled(scattering_value / pdf_val, &lambdas));
+            self.cross_interface(&mut media, &rec, &scattered.direction(), &lambdas);
+
+            specular_bounce = false;
+            prev_p = rec.p;
+            prev_pdf = pdf_val;
+            ray = scattered;
         }
-        let mut rec = HitRecord::new();
+        radiance
+    }
 
-        if !world.hit(&r,&Interval::new(0.001, f64::INFINITY),&mut rec) {
-            return self.background;
+    fn sampled(&self, color:Vector3<f64>, lambdas:&Vector3<f64>) -> Vector3<f64> {
+        if self.spectral {rgb_to_spectrum(&color, lambdas)} else {color}
+    }
+
+    // Absorbing interfaces form a stack of nested media: transmitting through
+    // a front face enters the material's medium, through a back face leaves
+    // it. Non-absorbing dielectrics are not tracked, so a clear object nested
+    // inside absorbing glass still absorbs with the outer coefficient, and a
+    // path that escapes an open mesh keeps its last medium.
+    fn cross_interface(&self, media:&mut Vec<Vector3<f64>>, rec:&HitRecord, direction:&Vector3<f64>, lambdas:&Vector3<f64>) {
+        let absorption = rec.mat.absorption(rec);
+        if absorption == Vector3::zeros() || direction.dot(&rec.normal) >= 0.0 {
+            return;
+        }
+        if rec.front_face {
+            media.push(self.sampled(absorption, lambdas));
+        } else {
+            media.pop();
         }
+    }
 
-        let mut srec = ScatterRecord::new();
-        let color_from_emission = rec.mat.emitted(&r, &rec, rec.u, rec.v, &rec.p);
-        if !rec.mat.scatter(&r, &rec, &mut srec) {
-            return color_from_emission;
+    pub fn sample_direct(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, world:&Arc<dyn Hittable>, lights:&Arc<dyn Hittable>, state:&PathState) -> Vector3<f64> {
+        let light_pdf = HittablePdf::new(lights.clone(), rec.p);
+        let direction = light_pdf.generate();
+        let light_pdf_val = light_pdf.value(&direction);
+        if light_pdf_val <= 0.0 {
+            return Vector3::zeros();
         }
 
-        if srec.skip_pdf {
-            return srec.attenuation.component_mul(&Self::ray_color(&self, &srec.skip_pdf_ray, depth-1,  &world, &lights));
+        let shadow_ray = Ray::initial(rec.p, direction, r_in.time());
+        let scattering_value = rec.mat.scattering_value(r_in, rec, srec, &shadow_ray);
+        if scattering_value == Vector3::zeros() {
+            return Vector3::zeros();
         }
 
-        let light_ptr = Arc::new(HittablePdf::new(lights.clone(), rec.p.clone()));
-        let p = MixturePdf::new(light_ptr, srec.pdf_ptr.clone());
+        let mut light_rec = HitRecord::new();
+        let light_emission = if world.hit(&shadow_ray, &Interval::new(0.001, f64::INFINITY), &mut light_rec) {
+            let emitted = light_rec.mat.emitted(&shadow_ray, &light_rec, light_rec.u, light_rec.v, &light_rec.p);
+            emitted.component_mul(&transmittance(&state.absorption, light_rec.t * direction.norm()))
+        } else if let Some(environment) = &self.environment {
+            environment.radiance(&direction)
+        } else {
+            return Vector3::zeros();
+        };
 
-        let scattered = Ray::initial(rec.p.clone(), p.generate(), r.time());
-        let pdf_val = p.value(&scattered.direction()); 
+        let weight = power_heuristic(1, light_pdf_val, 1, srec.pdf_ptr.value(&direction));
+        self.sampled(scattering_value, &state.lambdas).component_mul(&self.sampled(light_emission, &state.lambdas)) * weight / light_pdf_val
+    }
 
-        let scattering_pdf = rec.mat.scattering_pdf(&r, &rec, &scattered);
-        
-        let sample_color = Self::ray_color(&self, &scattered, depth-1, &world, &lights);
-        let color_from_scatter = (srec.attenuation * scattering_pdf).component_mul(&sample_color) / pdf_val;
-        color_from_emission + color_from_scatter
+    pub fn sample_delta_lights(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, world:&Arc<dyn Hittable>, state:&PathState) -> Vector3<f64> {
+        let mut color = Vector3::zeros();
+        for light in self.delta_lights.iter() {
+            let sample = match light.sample_li(&rec.p) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let shadow_ray = Ray::initial(rec.p, sample.direction, r_in.time());
+            let scattering_value = rec.mat.scattering_value(r_in, rec, srec, &shadow_ray);
+            if scattering_value == Vector3::zeros() {
+                continue;
+            }
+            let mut shadow_rec = HitRecord::new();
+            if world.hit(&shadow_ray, &Interval::new(0.001, sample.distance - 0.001), &mut shadow_rec) {
+                continue;
+            }
+            let radiance = self.sampled(sample.radiance, &state.lambdas).component_mul(&transmittance(&state.absorption, sample.distance));
+            color += self.sampled(scattering_value, &state.lambdas).component_mul(&radiance);
+        }
+        color
     }
 
-    pub fn render(&mut self, world:&Arc<dyn Hittable>, lights:&Arc<dyn Hittable>) {
+    pub fn render(&mut self, world:&Arc<dyn Hittable>, lights:&Arc<dyn Hittable>) -> ImageResult<Framebuffer> {
         self.initialize();
-        println!("P3");
-        println!("{} {}",self.image_width, self.image_height);
-        println!("255");
-        let wself = Arc::new(self.clone());
-        let wworld = Arc::new(world.clone());
-        let wlights = Arc::new(lights.clone());
-
-        let mut pb = ProgressBar::new(self.image_height as u64);
+        let mut framebuffer = Framebuffer::new(self.image_width, self.image_height);
+        let tiles = Tile::split(self.image_width, self.image_height, self.tile_size);
+        let next_tile = AtomicUsize::new(0);
+
+        let pb = ProgressBar::new(tiles.len() as u64);
         pb.set_style(ProgressStyle::default_bar()
             .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {percent:>7}%"));
-        for j in 0..self.image_height {
-            pb.set_position(j as u64);
-            let mut line_pixel_color = Arc::new(Mutex::new(Vec::new()));
-            for i in 0..self.image_width {
-                line_pixel_color.lock().unwrap().push(Vector3::new(0.0,0.0,0.0));
-            }
-            let thread_num:i64 = 28;
-            let mut threads = Vec::new();
-            for k in 0..thread_num {
-                let wself = Arc::clone(&wself);
-                let wworld = Arc::clone(&wworld);
-                let wlights = Arc::clone(&wlights);
-                let mut line_pixel_color = Arc::clone(&line_pixel_color);
-                let render_thread = thread::spawn(move || {
-                    let mut i = k;
-                    while i < wself.image_width {
-                        let mut pixel_color = Vector3::new(0.0,0.0,0.0);
-                        for s_j in 0..wself.sqrt_spp{
-                            for s_i in 0..wself.sqrt_spp {
-                                let r = Self::get_ray(&wself, i, j, s_i, s_j);
-                                pixel_color += Self::ray_color(&wself, &r, wself.max_depth, &wworld, &wlights);
-                            }
+
+        let cam = &*self;
+        thread::scope(|s| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..cam.threads.max(1) {
+                let tx = tx.clone();
+                let tiles = &tiles;
+                let next_tile = &next_tile;
+                s.spawn(move || {
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if index >= tiles.len() {
+                            break;
+                        }
+                        let pixels = cam.render_tile(&tiles[index], world, lights);
+                        if tx.send((index, pixels)).is_err() {
+                            break;
                         }
-                        (line_pixel_color.lock().unwrap())[i as usize] = pixel_color.clone();
-                        i += thread_num;
                     }
                 });
-                threads.push(render_thread);
             }
-            for render_thread in threads {
-                render_thread.join().unwrap();
+            drop(tx);
+
+            for (index, pixels) in rx {
+                let tile = &tiles[index];
+                for j in tile.y0..tile.y1 {
+                    for i in tile.x0..tile.x1 {
+                        let k = ((j - tile.y0) * tile.width() + (i - tile.x0)) as usize;
+                        framebuffer.set(i, j, pixels[k]);
+                    }
+                }
+                pb.inc(1);
             }
-            let pixel_color = line_pixel_color.lock().unwrap().clone();
-            for i in 0..self.image_width {
-                write_color(&(self.pixel_samples_scale * pixel_color[i as usize]));
+        });
+        pb.finish_and_clear();
+
+        save_image(&framebuffer, &self.output_file, self.bit_depth)?;
+        Ok(framebuffer)
+    }
+
+    pub fn render_tile(&self, tile:&Tile, world:&Arc<dyn Hittable>, lights:&Arc<dyn Hittable>) -> Vec<Vector3<f64>> {
+        let mut pixels = Vec::with_capacity((tile.width() * tile.height()) as usize);
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let mut pixel_color = Vector3::new(0.0,0.0,0.0);
+                for s_j in 0..self.sqrt_spp {
+                    for s_i in 0..self.sqrt_spp {
+                        seed_sample(self.seed, i, j, s_j * self.sqrt_spp + s_i);
+                        let r = self.get_ray(i, j, s_i, s_j);
+                        let sample = self.ray_color(&r, self.max_depth, world, lights);
+                        pixel_color += if self.spectral {
+                            spectrum_to_rgb(&sample, &wavelengths(r.wavelength))
+                        } else {
+                            sample
+                        };
+                    }
+                }
+                pixels.push(self.pixel_samples_scale * pixel_color);
             }
         }
-        pb.finish_and_clear();
+        pixels
     }
 
     pub fn initialize(&mut self) {
@@ -197,7 +366,12 @@ impl Camera {
         }
         let ray_direction = pixel_sample - ray_origin;
         let ray_time = random_double();
-        Ray::initial(ray_origin, ray_direction, ray_time)
+        let ray = Ray::initial(ray_origin, ray_direction, ray_time);
+        if self.spectral {
+            ray.with_wavelength(sample_wavelength(random_double()))
+        } else {
+            ray
+        }
     }
 
     pub fn sample_square_straitified(&self, s_i:i64, s_j:i64) -> Vector3<f64> {
@@ -215,4 +389,8 @@ impl Camera {
         let p = random_in_unit_disk();
         self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
+}
+
+fn transmittance(absorption:&Vector3<f64>, distance:f64) -> Vector3<f64> {
+    absorption.map(|a| if a > 0.0 {(-a * distance).exp()} else {1.0})
 }
\ No newline at end of file
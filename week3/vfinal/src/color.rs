@@ -8,25 +8,30 @@ pub fn linear_to_gamma(linear_component:f64) -> f64 {
     0.0
 }
 
-pub fn write_color(pixel_color:&Vector3<f64>) {
-    let mut r = pixel_color.x;
-    let mut g = pixel_color.y;
-    let mut b = pixel_color.z;
-    if r != r{
-        r = 0.0;
-    }
-    if g != g {
-        g = 0.0;
-    }
-    if b != b {
-        b = 0.0;
-    }
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+pub fn sanitize(pixel_color:&Vector3<f64>) -> Vector3<f64> {
+    pixel_color.map(|c| if c.is_finite() { c } else { 0.0 })
+}
+
+pub fn to_display(pixel_color:&Vector3<f64>) -> Vector3<f64> {
+    let intensity = Interval::new(0.0, 1.0);
+    sanitize(pixel_color).map(|c| intensity.clamp(linear_to_gamma(c)))
+}
+
+pub fn to_rgb8(pixel_color:&Vector3<f64>) -> [u8;3] {
+    let c = to_display(pixel_color);
     let intensity = Interval::new(0.000,0.999);
-    let rbyte = (256.0 * intensity.clamp(r)) as i64;
-    let gbyte = (256.0 * intensity.clamp(g)) as i64;
-    let bbyte = (256.0 * intensity.clamp(b)) as i64;
-    println!("{} {} {}",rbyte,gbyte,bbyte);
-}
\ No newline at end of file
+    [
+        (256.0 * intensity.clamp(c.x)) as u8,
+        (256.0 * intensity.clamp(c.y)) as u8,
+        (256.0 * intensity.clamp(c.z)) as u8,
+    ]
+}
+
+pub fn to_rgb16(pixel_color:&Vector3<f64>) -> [u16;3] {
+    let c = to_display(pixel_color);
+    [
+        (65535.0 * c.x).round() as u16,
+        (65535.0 * c.y).round() as u16,
+        (65535.0 * c.z).round() as u16,
+    ]
+}
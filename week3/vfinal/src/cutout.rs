@@ -0,0 +1,145 @@
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light_sampler::LightBounds;
+use crate::ray::Ray;
+use crate::rtweekend::random_double;
+use crate::texture::Texture;
+
+const MAX_PASSES:usize = 64;
+
+pub struct Cutout {
+    object:Arc<dyn Hittable>,
+    alpha:Arc<dyn Texture>,
+    threshold:f64,
+    stochastic:bool,
+}
+
+impl Cutout {
+    pub fn new (object:Arc<dyn Hittable>, alpha:Arc<dyn Texture>, threshold:f64) -> Self {
+        Cutout {
+            object,
+            alpha,
+            threshold,
+            stochastic:false,
+        }
+    }
+
+    pub fn stochastic (object:Arc<dyn Hittable>, alpha:Arc<dyn Texture>) -> Self {
+        Cutout {
+            object,
+            alpha,
+            threshold:0.0,
+            stochastic:true,
+        }
+    }
+
+    fn is_opaque(&self, rec:&HitRecord) -> bool {
+        let alpha = self.alpha.alpha(rec.u, rec.v, &rec.p);
+        if self.stochastic {
+            alpha >= 1.0 || random_double() < alpha
+        } else {
+            alpha >= self.threshold
+        }
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        let mut temp_rec = HitRecord::new();
+        let mut t_min = ray_t.min;
+        for _ in 0..MAX_PASSES {
+            if !self.object.hit(r, &Interval::new(t_min, ray_t.max), &mut temp_rec) {
+                return false;
+            }
+            if self.is_opaque(&temp_rec) {
+                *rec = temp_rec;
+                return true;
+            }
+            t_min = temp_rec.t + 0.0001;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    // Light sampling ignores alpha: cut-away regions are still sampled and
+    // their samples are then rejected by the shadow ray, which stays unbiased
+    // but wastes samples on mostly transparent emitters.
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        self.object.random(origin)
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        self.object.light_bounds()
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        self.object.clone().collect_emitters(emitters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::LinearBvh;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::texture::SolidColor;
+
+    fn quad(q:Vector3<f64>, u:Vector3<f64>, v:Vector3<f64>) -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(q, u, v, Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn flat() -> Arc<dyn Hittable> {
+        quad(Vector3::new(-1.0, -1.0, 0.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0))
+    }
+
+    fn tilted() -> Arc<dyn Hittable> {
+        quad(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 2.0))
+    }
+
+    fn transparent(object:Arc<dyn Hittable>) -> Arc<dyn Hittable> {
+        Arc::new(Cutout::new(object, Arc::new(SolidColor::new(Vector3::zeros())), 0.5))
+    }
+
+    // Both quads share a centroid, so the BVH keeps them in a single leaf.
+    fn closest_hit(objects:Vec<Arc<dyn Hittable>>, y:f64) -> Option<f64> {
+        let mut list = HittableList::new();
+        for object in objects {
+            list.add(object);
+        }
+        let bvh = LinearBvh::initial(&mut list);
+        let r = Ray::new(Vector3::new(0.0, y, -10.0), Vector3::new(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::new();
+        if bvh.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec) {
+            Some(rec.t)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn rejected_cutout_hit_keeps_opaque_record() {
+        for y in [0.5, -0.5] {
+            let expected = Some(10.0 + y);
+            assert_eq!(closest_hit(vec![tilted(), transparent(flat())], y), expected);
+            assert_eq!(closest_hit(vec![transparent(flat()), tilted()], y), expected);
+        }
+    }
+
+    #[test]
+    fn fully_cut_out_object_is_not_hit() {
+        assert_eq!(closest_hit(vec![transparent(flat())], 0.5), None);
+    }
+}
@@ -0,0 +1,233 @@
+use nalgebra::Vector3;
+use std::fs;
+use std::path::Path;
+
+use crate::perlin::Perlin;
+
+pub trait DensityField: Send + Sync {
+    fn density(&self, p:&Vector3<f64>) -> f64;
+    fn max_density(&self) -> f64;
+}
+
+pub struct TurbulenceDensity {
+    noise:Perlin,
+    scale:f64,
+    depth:i64,
+    max:f64,
+}
+
+impl TurbulenceDensity {
+    pub fn new (scale:f64, depth:i64) -> Self {
+        TurbulenceDensity {
+            noise:Perlin::new(),
+            scale,
+            depth,
+            max:2.0 * (1.0 - 0.5f64.powi(depth as i32)),
+        }
+    }
+}
+
+impl DensityField for TurbulenceDensity {
+    fn density(&self, p:&Vector3<f64>) -> f64 {
+        self.noise.turb(&(self.scale * p), self.depth).min(self.max)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max
+    }
+}
+
+pub struct VoxelGrid {
+    nx:usize,
+    ny:usize,
+    nz:usize,
+    data:Vec<f64>,
+    min:Vector3<f64>,
+    max:Vector3<f64>,
+    max_density:f64,
+}
+
+fn read_i32(bytes:&[u8], offset:usize) -> i32 {
+    i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn read_f32(bytes:&[u8], offset:usize) -> f64 {
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]) as f64
+}
+
+impl VoxelGrid {
+    pub fn load(path:&Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(bytes:&[u8]) -> Result<Self, String> {
+        const HEADER:usize = 48;
+        if bytes.len() < HEADER || &bytes[0..3] != b"VOL" {
+            return Err("not a VOL volume file".to_string());
+        }
+        if bytes[3] != 3 {
+            return Err(format!("unsupported VOL version {}", bytes[3]));
+        }
+        if read_i32(bytes, 4) != 1 {
+            return Err("only float32 VOL data is supported".to_string());
+        }
+        let dims = [read_i32(bytes, 8), read_i32(bytes, 12), read_i32(bytes, 16), read_i32(bytes, 20)];
+        if dims.iter().any(|&d| d <= 0) {
+            return Err("VOL resolution and channel count must be positive".to_string());
+        }
+        let [nx, ny, nz, channels] = dims.map(|d| d as usize);
+        let min = Vector3::new(read_f32(bytes, 24), read_f32(bytes, 28), read_f32(bytes, 32));
+        let max = Vector3::new(read_f32(bytes, 36), read_f32(bytes, 40), read_f32(bytes, 44));
+        if (0..3).any(|i| max[i] <= min[i]) {
+            return Err("VOL bounding box is empty".to_string());
+        }
+
+        let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz))
+            .ok_or_else(|| "VOL resolution is too large".to_string())?;
+        let size = count.checked_mul(channels).and_then(|n| n.checked_mul(4)).and_then(|n| n.checked_add(HEADER))
+            .ok_or_else(|| "VOL resolution is too large".to_string())?;
+        if bytes.len() < size {
+            return Err(format!("VOL data is truncated, expected {} values", count * channels));
+        }
+        let data:Vec<f64> = (0..count)
+            .map(|i| read_f32(bytes, HEADER + i * channels * 4).max(0.0))
+            .collect();
+        let max_density = data.iter().fold(0.0, |a:f64, &b| a.max(b));
+        Ok(VoxelGrid {
+            nx,
+            ny,
+            nz,
+            data,
+            min,
+            max,
+            max_density,
+        })
+    }
+
+    fn voxel(&self, x:usize, y:usize, z:usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+}
+
+impl DensityField for VoxelGrid {
+    fn density(&self, p:&Vector3<f64>) -> f64 {
+        if (0..3).any(|i| p[i] < self.min[i] || p[i] > self.max[i]) {
+            return 0.0;
+        }
+        let n = [self.nx, self.ny, self.nz];
+        let mut index = [0usize;3];
+        let mut frac = [0.0;3];
+        for i in 0..3 {
+            let g = ((p[i] - self.min[i]) / (self.max[i] - self.min[i]) * n[i] as f64 - 0.5).clamp(0.0, (n[i] - 1) as f64);
+            index[i] = (g as usize).min(n[i].saturating_sub(2));
+            frac[i] = if n[i] > 1 {g - index[i] as f64} else {0.0};
+        }
+        let next = |i:usize| (index[i] + 1).min(n[i] - 1);
+
+        let mut density = 0.0;
+        for (dz, wz) in [(index[2], 1.0 - frac[2]), (next(2), frac[2])] {
+            for (dy, wy) in [(index[1], 1.0 - frac[1]), (next(1), frac[1])] {
+                for (dx, wx) in [(index[0], 1.0 - frac[0]), (next(0), frac[0])] {
+                    density += wx * wy * wz * self.voxel(dx, dy, dz);
+                }
+            }
+        }
+        density
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_density
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vol(dims:[i32;4], bbox:[f32;6], data:&[f32]) -> Vec<u8> {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        bytes.extend_from_slice(&1i32.to_le_bytes());
+        for d in dims {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+        for v in bbox.iter().chain(data.iter()) {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn unit_cube(dims:[i32;4], data:&[f32]) -> Vec<u8> {
+        vol(dims, [0.0, 0.0, 0.0, 1.0, 1.0, 1.0], data)
+    }
+
+    #[test]
+    fn parses_grid_and_reports_max() {
+        let grid = VoxelGrid::parse(&unit_cube([2, 1, 1, 1], &[0.5, 2.0])).unwrap();
+        assert_eq!((grid.nx, grid.ny, grid.nz), (2, 1, 1));
+        assert_eq!(grid.max_density(), 2.0);
+    }
+
+    #[test]
+    fn reads_first_channel_only() {
+        let grid = VoxelGrid::parse(&unit_cube([2, 1, 1, 2], &[1.0, 9.0, 3.0, 9.0])).unwrap();
+        assert_eq!(grid.data, vec![1.0, 3.0]);
+        assert_eq!(grid.max_density(), 3.0);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let good = unit_cube([1, 1, 1, 1], &[1.0]);
+        let mut magic = good.clone();
+        magic[0] = b'X';
+        assert!(VoxelGrid::parse(&magic).is_err());
+        let mut version = good.clone();
+        version[3] = 2;
+        assert!(VoxelGrid::parse(&version).is_err());
+        let mut encoding = good.clone();
+        encoding[4] = 3;
+        assert!(VoxelGrid::parse(&encoding).is_err());
+        assert!(VoxelGrid::parse(&good[..40]).is_err());
+        assert!(VoxelGrid::parse(&unit_cube([0, 1, 1, 1], &[])).is_err());
+        assert!(VoxelGrid::parse(&vol([1, 1, 1, 1], [0.0, 0.0, 0.0, 0.0, 1.0, 1.0], &[1.0])).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_data() {
+        assert!(VoxelGrid::parse(&unit_cube([2, 2, 2, 1], &[1.0;7])).is_err());
+        let huge = 2_000_000_000;
+        assert!(VoxelGrid::parse(&unit_cube([huge, huge, huge, 1], &[])).is_err());
+        assert!(VoxelGrid::parse(&unit_cube([huge, 1, 1, huge], &[])).is_err());
+    }
+
+    #[test]
+    fn interpolates_trilinearly() {
+        let data:Vec<f32> = (0..8).map(|i| i as f32).collect();
+        let grid = VoxelGrid::parse(&unit_cube([2, 2, 2, 1], &data)).unwrap();
+        let at = |x:f64, y:f64, z:f64| grid.density(&Vector3::new(x, y, z));
+        assert_eq!(at(0.25, 0.25, 0.25), 0.0);
+        assert_eq!(at(0.75, 0.25, 0.25), 1.0);
+        assert_eq!(at(0.25, 0.75, 0.25), 2.0);
+        assert_eq!(at(0.25, 0.25, 0.75), 4.0);
+        assert_eq!(at(0.75, 0.75, 0.75), 7.0);
+        assert!((at(0.5, 0.5, 0.5) - 3.5).abs() < 1e-12);
+        assert!((at(0.5, 0.25, 0.25) - 0.5).abs() < 1e-12);
+        assert_eq!(at(0.1, 0.1, 0.1), 0.0);
+        assert_eq!(at(1.5, 0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn turbulence_stays_below_max_density() {
+        for depth in [1, 3, 7] {
+            let field = TurbulenceDensity::new(4.0, depth);
+            let max = field.max_density();
+            for i in 0..2000 {
+                let f = i as f64;
+                let p = Vector3::new((f * 0.731) % 20.0 - 10.0, (f * 0.377) % 20.0 - 10.0, (f * 0.913) % 20.0 - 10.0);
+                let d = field.density(&p);
+                assert!((0.0..=max).contains(&d), "density {} outside [0, {}]", d, max);
+            }
+        }
+    }
+}
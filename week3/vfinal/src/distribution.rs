@@ -0,0 +1,92 @@
+pub struct Distribution1D {
+    pub func:Vec<f64>,
+    pub cdf:Vec<f64>,
+    pub func_int:f64,
+}
+
+impl Distribution1D {
+    pub fn new (func:Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if func_int > 0.0 {*c / func_int} else {i as f64 / n as f64};
+        }
+        Distribution1D {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    fn offset(&self, u:f64) -> usize {
+        let last = self.count().saturating_sub(1);
+        self.cdf.partition_point(|&c| c <= u).saturating_sub(1).min(last)
+    }
+
+    pub fn sample_continuous(&self, u:f64) -> (f64, f64, usize) {
+        let offset = self.offset(u);
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+        let pdf = if self.func_int > 0.0 {self.func[offset] / self.func_int} else {1.0};
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    pub fn sample_discrete(&self, u:f64) -> (usize, f64) {
+        let offset = self.offset(u);
+        (offset, self.discrete_pdf(offset))
+    }
+
+    pub fn discrete_pdf(&self, index:usize) -> f64 {
+        if self.func_int > 0.0 {
+            self.func[index].max(0.0) / (self.func_int * self.count() as f64)
+        } else {
+            1.0 / self.count() as f64
+        }
+    }
+}
+
+pub struct Distribution2D {
+    conditional:Vec<Distribution1D>,
+    marginal:Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new (func:&[f64], nu:usize, nv:usize) -> Self {
+        let conditional:Vec<Distribution1D> = (0..nv)
+            .map(|v| Distribution1D::new(func[v * nu..(v + 1) * nu].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.func_int).collect());
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    pub fn sample_continuous(&self, u0:f64, u1:f64) -> (f64, f64, f64) {
+        let (v, pdf_v, iv) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[iv].sample_continuous(u0);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u:f64, v:f64) -> f64 {
+        if self.marginal.func_int <= 0.0 {
+            return 1.0;
+        }
+        let nv = self.marginal.count();
+        let iv = ((v * nv as f64) as usize).min(nv - 1);
+        let nu = self.conditional[iv].count();
+        let iu = ((u * nu as f64) as usize).min(nu - 1);
+        self.conditional[iv].func[iu].max(0.0) / self.marginal.func_int
+    }
+}
@@ -0,0 +1,46 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+pub trait EmissionProfile: Send + Sync {
+    fn value(&self, w:&Vector3<f64>) -> f64;
+    fn integral(&self) -> f64;
+    fn sample(&self, u0:f64, u1:f64) -> (Vector3<f64>, f64);
+    fn pdf(&self, w:&Vector3<f64>) -> f64;
+}
+
+pub struct CosinePower {
+    exponent:f64,
+}
+
+impl CosinePower {
+    pub fn new (exponent:f64) -> Self {
+        CosinePower {
+            exponent:exponent.max(0.0),
+        }
+    }
+}
+
+impl EmissionProfile for CosinePower {
+    fn value(&self, w:&Vector3<f64>) -> f64 {
+        if w.z <= 0.0 {
+            return 0.0;
+        }
+        w.z.powf(self.exponent)
+    }
+
+    fn integral(&self) -> f64 {
+        2.0 * PI / (self.exponent + 2.0)
+    }
+
+    fn sample(&self, u0:f64, u1:f64) -> (Vector3<f64>, f64) {
+        let cos_theta = u0.powf(1.0 / (self.exponent + 1.0));
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u1;
+        let direction = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        (direction, self.pdf(&direction))
+    }
+
+    fn pdf(&self, w:&Vector3<f64>) -> f64 {
+        (self.exponent + 1.0) / (2.0 * PI) * self.value(w)
+    }
+}
@@ -0,0 +1,146 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::distribution::Distribution2D;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::light_sampler::LightBounds;
+use crate::ray::Ray;
+use crate::rtw_stb_image::RtwImage;
+use crate::rtweekend::random_double;
+
+pub trait Environment: Send + Sync {
+    fn radiance(&self, direction:&Vector3<f64>) -> Vector3<f64>;
+    fn pdf_value(&self, direction:&Vector3<f64>) -> f64;
+    fn random(&self) -> Vector3<f64>;
+}
+
+pub fn direction_to_uv(d:&Vector3<f64>) -> (f64, f64) {
+    let d = d.normalize();
+    let theta = d.y.clamp(-1.0, 1.0).acos();
+    let phi = (-d.z).atan2(d.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+pub fn uv_to_direction(u:f64, v:f64) -> Vector3<f64> {
+    let phi = u * 2.0 * PI;
+    let theta = v * PI;
+    let sin_theta = theta.sin();
+    Vector3::new(-phi.cos() * sin_theta, theta.cos(), phi.sin() * sin_theta)
+}
+
+pub struct ImageEnvironment {
+    width:usize,
+    height:usize,
+    pixels:Vec<Vector3<f64>>,
+    intensity:f64,
+    sin_theta:f64,
+    cos_theta:f64,
+    distribution:Distribution2D,
+}
+
+impl ImageEnvironment {
+    pub fn load (path:&Path, intensity:f64, rotation:f64) -> Option<Self> {
+        let image = RtwImage::load(path)?;
+        let data = image.data?.to_rgb32f();
+        let width = data.width() as usize;
+        let height = data.height() as usize;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let pixels:Vec<Vector3<f64>> = data.pixels()
+            .map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64).map(|c| c.max(0.0)))
+            .collect();
+
+        let mut func = Vec::with_capacity(width * height);
+        for j in 0..height {
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let c = pixels[j * width + i];
+                func.push((0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z) * sin_theta);
+            }
+        }
+
+        let radians = rotation.to_radians();
+        Some(ImageEnvironment {
+            width,
+            height,
+            pixels,
+            intensity,
+            sin_theta:radians.sin(),
+            cos_theta:radians.cos(),
+            distribution:Distribution2D::new(&func, width, height),
+        })
+    }
+
+    fn to_local(&self, d:&Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * d.x - self.sin_theta * d.z, d.y, self.sin_theta * d.x + self.cos_theta * d.z)
+    }
+
+    fn to_world(&self, d:&Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * d.x + self.sin_theta * d.z, d.y, -self.sin_theta * d.x + self.cos_theta * d.z)
+    }
+}
+
+impl Environment for ImageEnvironment {
+    fn radiance(&self, direction:&Vector3<f64>) -> Vector3<f64> {
+        let (u, v) = direction_to_uv(&self.to_local(direction));
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.intensity * self.pixels[j * self.width + i]
+    }
+
+    fn pdf_value(&self, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let (u, v) = direction_to_uv(&self.to_local(direction));
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Vector3<f64> {
+        let (u, v, _) = self.distribution.sample_continuous(random_double(), random_double());
+        self.to_world(&uv_to_direction(u, v))
+    }
+}
+
+pub struct EnvironmentLight {
+    environment:Arc<dyn Environment>,
+}
+
+impl EnvironmentLight {
+    pub fn new (environment:Arc<dyn Environment>) -> Self {
+        EnvironmentLight {
+            environment,
+        }
+    }
+}
+
+impl Hittable for EnvironmentLight {
+    fn hit (&self, _r:&Ray, _ray_t:&Interval, _rec:&mut HitRecord) -> bool {
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::empty()
+    }
+
+    fn pdf_value(&self, _origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        self.environment.pdf_value(direction)
+    }
+
+    fn random (&self, _origin:&Vector3<f64>) -> Vector3<f64> {
+        self.environment.random()
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
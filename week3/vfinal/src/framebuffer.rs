@@ -0,0 +1,22 @@
+use nalgebra::Vector3;
+
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width:i64,
+    pub height:i64,
+    pub pixels:Vec<Vector3<f64>>,
+}
+
+impl Framebuffer {
+    pub fn new(width:i64, height:i64) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels:vec![Vector3::zeros(); (width * height) as usize],
+        }
+    }
+
+    pub fn set(&mut self, i:i64, j:i64, color:Vector3<f64>) {
+        self.pixels[(j * self.width + i) as usize] = color;
+    }
+}
@@ -0,0 +1,151 @@
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, density::DensityField, hittable::{HitRecord, Hittable}, interval::Interval, material::{Isotropic, Material}, ray::Ray, rtweekend::random_double, texture::Texture};
+
+struct ScaledTexture {
+    tex:Arc<dyn Texture>,
+    scale:f64,
+}
+
+impl Texture for ScaledTexture {
+    fn value (&self, u:f64, v:f64, p:&Vector3<f64>) -> Vector3<f64> {
+        self.tex.value(u, v, p) * self.scale
+    }
+}
+
+pub struct HeterogeneousMedium {
+    boundary:Arc<dyn Hittable>,
+    density:Arc<dyn DensityField>,
+    max_density:f64,
+    majorant:f64,
+    phase_function:Arc<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    pub fn new (boundary:Arc<dyn Hittable>, density:Arc<dyn DensityField>, sigma_a:f64, sigma_s:f64, albedo:Arc<dyn Texture>) -> Self {
+        let sigma_t = sigma_a + sigma_s;
+        let max_density = density.max_density();
+        let single_scattering_albedo = if sigma_t > 0.0 {sigma_s / sigma_t} else {0.0};
+        HeterogeneousMedium {
+            boundary,
+            density,
+            max_density,
+            majorant:sigma_t * max_density,
+            phase_function:Arc::new(Isotropic::new(Arc::new(ScaledTexture {
+                tex:albedo,
+                scale:single_scattering_albedo,
+            }))),
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        if self.majorant <= 0.0 {
+            return false;
+        }
+
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+        if !self.boundary.hit(r, &Interval::universe(), &mut rec1) {
+            return false;
+        }
+        if !self.boundary.hit(r, &Interval::new(rec1.t + 0.0001, f64::INFINITY), &mut rec2) {
+            return false;
+        }
+
+        let t_min = rec1.t.max(ray_t.min).max(0.0);
+        let t_max = rec2.t.min(ray_t.max);
+        if t_min >= t_max {
+            return false;
+        }
+
+        let inv_majorant = 1.0 / (self.majorant * r.direction().norm());
+        let mut t = t_min;
+        loop {
+            t -= (1.0 - random_double()).ln() * inv_majorant;
+            if t >= t_max {
+                return false;
+            }
+            let p = r.at(t);
+            if random_double() * self.max_density < self.density.density(&p) {
+                rec.t = t;
+                rec.p = p;
+                rec.normal = Vector3::new(1.0,0.0,0.0);
+                rec.front_face = true;
+                rec.mat = self.phase_function.clone();
+                return true;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::ScatterRecord;
+    use crate::rtweekend::seed_rng;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    struct HalfSpaceDensity {
+        value:f64,
+    }
+
+    impl DensityField for HalfSpaceDensity {
+        fn density(&self, p:&Vector3<f64>) -> f64 {
+            if p.z > 0.0 {self.value} else {0.0}
+        }
+
+        fn max_density(&self) -> f64 {
+            1.0
+        }
+    }
+
+    fn collision_rate(medium:&HeterogeneousMedium, trials:usize) -> f64 {
+        seed_rng(7, 0);
+        let r = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 2.0));
+        let mut hits = 0;
+        for _ in 0..trials {
+            let mut rec = HitRecord::new();
+            if medium.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec) {
+                assert!(rec.p.z > 0.0 && rec.p.z < 1.0);
+                hits += 1;
+            }
+        }
+        hits as f64 / trials as f64
+    }
+
+    #[test]
+    fn delta_tracking_matches_beer_lambert() {
+        let boundary:Arc<dyn Hittable> = Arc::new(Sphere::new(Vector3::zeros(), 1.0, Arc::new(Isotropic::initial(Vector3::zeros()))));
+        let albedo:Arc<dyn Texture> = Arc::new(SolidColor::new(Vector3::new(1.0, 1.0, 1.0)));
+        let trials = 100_000;
+        for (value, sigma_a, sigma_s) in [(0.5, 0.3, 0.9), (1.0, 0.0, 2.0), (0.25, 1.0, 0.0)] {
+            let density = Arc::new(HalfSpaceDensity { value });
+            let medium = HeterogeneousMedium::new(boundary.clone(), density, sigma_a, sigma_s, albedo.clone());
+            let expected = 1.0 - (-(sigma_a + sigma_s) * value).exp();
+            let tolerance = 4.0 * (expected * (1.0 - expected) / trials as f64).sqrt();
+            let rate = collision_rate(&medium, trials);
+            assert!((rate - expected).abs() < tolerance, "rate {} expected {}", rate, expected);
+        }
+    }
+
+    #[test]
+    fn scattering_albedo_is_weighted_by_single_scattering_ratio() {
+        let boundary:Arc<dyn Hittable> = Arc::new(Sphere::new(Vector3::zeros(), 1.0, Arc::new(Isotropic::initial(Vector3::zeros()))));
+        let albedo:Arc<dyn Texture> = Arc::new(SolidColor::new(Vector3::new(0.8, 0.8, 0.8)));
+        let medium = HeterogeneousMedium::new(boundary, Arc::new(HalfSpaceDensity { value:1.0 }), 1.0, 3.0, albedo);
+        let mut rec = HitRecord::new();
+        rec.p = Vector3::new(0.0, 0.0, 0.5);
+        let r = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let mut srec = ScatterRecord::new();
+        assert!(medium.phase_function.scatter(&r, &rec, &mut srec));
+        assert!((srec.attenuation - Vector3::new(0.6, 0.6, 0.6)).norm() < 1e-12);
+    }
+}
@@ -1,4 +1,4 @@
-use nalgebra::Vector3;
+use nalgebra::{Matrix3, Matrix4, Unit, Vector3};
 use std::sync::Arc;
 
 use crate::ray::Ray;
@@ -6,6 +6,7 @@ use crate::interval::Interval;
 use crate::material::{Material,Lambertian};
 use crate::aabb::Aabb;
 use crate::texture::Texture;
+use crate::light_sampler::LightBounds;
 
 #[derive(Clone)]
 pub struct HitRecord {
@@ -15,6 +16,8 @@ pub struct HitRecord {
     pub t:f64,
     pub u:f64,
     pub v:f64,
+    pub dpdu:Vector3<f64>,
+    pub dpdv:Vector3<f64>,
     pub front_face:bool,
 }
 
@@ -27,6 +30,8 @@ impl HitRecord {
             t:0.0,
             u:0.0,
             v:0.0,
+            dpdu:Vector3::new(0.0,0.0,0.0),
+            dpdv:Vector3::new(0.0,0.0,0.0),
             front_face:false,
         }
     }
@@ -52,6 +57,13 @@ pub trait Hittable: Send + Sync {
     fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
         Vector3::new(1.0,0.0,0.0)
     }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(self.bounding_box(), 0.0))
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+    }
 }
 
 pub struct Translate {
@@ -84,6 +96,28 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> Aabb {
         self.bbox.clone()
     }
+
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        self.object.pdf_value(&(origin - self.offset), direction)
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        self.object.random(&(origin - self.offset))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let mut bounds = self.object.light_bounds()?;
+        bounds.bounds = self.bbox.clone();
+        Some(bounds)
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = Vec::new();
+        self.object.clone().collect_emitters(&mut inner);
+        for object in inner {
+            emitters.push(Arc::new(Translate::new(object, self.offset)));
+        }
+    }
 }
 
 pub struct RotateY {
@@ -96,8 +130,10 @@ pub struct RotateY {
 impl RotateY {
     pub fn new(object:Arc<dyn Hittable>, angle:f64) -> Self {
         let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        Self::from_sin_cos(object, radians.sin(), radians.cos())
+    }
+
+    fn from_sin_cos(object:Arc<dyn Hittable>, sin_theta:f64, cos_theta:f64) -> Self {
         let bbox = object.bounding_box();
 
         let mut min = Vector3::new(std::f64::INFINITY, std::f64::INFINITY, std::f64::INFINITY);
@@ -133,37 +169,178 @@ impl RotateY {
     }
 }
 
-impl Hittable for RotateY {
-    fn hit (&self, r: &Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
+impl RotateY {
+    fn to_object(&self, v:&Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * v.x - self.sin_theta * v.z, v.y, self.sin_theta * v.x + self.cos_theta * v.z)
+    }
 
-        origin.x = self.cos_theta * r.origin().x - self.sin_theta * r.origin().z;
-        origin.z = self.sin_theta * r.origin().x + self.cos_theta * r.origin().z;
+    fn to_world(&self, v:&Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * v.x + self.sin_theta * v.z, v.y, -self.sin_theta * v.x + self.cos_theta * v.z)
+    }
+}
 
-        direction.x = self.cos_theta * r.direction().x - self.sin_theta * r.direction().z;
-        direction.z = self.sin_theta * r.direction().x + self.cos_theta * r.direction().z;
+impl Hittable for RotateY {
+    fn hit (&self, r: &Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        let origin = self.to_object(&r.origin());
+        let direction = self.to_object(&r.direction());
 
         let rotated_r = Ray::initial(origin, direction, r.time());
 
         if !self.object.hit(&rotated_r, &ray_t, rec) {
             return false;
         }
-        let mut p = rec.p.clone();
-        p.x = self.cos_theta * rec.p.x + self.sin_theta * rec.p.z;
-        p.z = -self.sin_theta * rec.p.x + self.cos_theta * rec.p.z;
+        rec.p = self.to_world(&rec.p);
+        rec.normal = self.to_world(&rec.normal);
+        rec.dpdu = self.to_world(&rec.dpdu);
+        rec.dpdv = self.to_world(&rec.dpdv);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        self.object.pdf_value(&self.to_object(origin), &self.to_object(direction))
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        self.to_world(&self.object.random(&self.to_object(origin)))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let mut bounds = self.object.light_bounds()?;
+        bounds.bounds = self.bbox.clone();
+        bounds.w = self.to_world(&bounds.w);
+        Some(bounds)
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = Vec::new();
+        self.object.clone().collect_emitters(&mut inner);
+        for object in inner {
+            emitters.push(Arc::new(RotateY::from_sin_cos(object, self.sin_theta, self.cos_theta)));
+        }
+    }
+}
+
+pub struct Transform {
+    object:Arc<dyn Hittable>,
+    matrix:Matrix4<f64>,
+    inverse:Matrix4<f64>,
+    linear:Matrix3<f64>,
+    inverse_linear:Matrix3<f64>,
+    abs_det:f64,
+    bbox:Aabb,
+}
+
+impl Transform {
+    pub fn new(object:Arc<dyn Hittable>, matrix:Matrix4<f64>) -> Option<Self> {
+        let inverse = matrix.try_inverse()?;
+        let linear = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+        let inverse_linear = inverse.fixed_view::<3, 3>(0, 0).into_owned();
+        let bbox = object.bounding_box();
+
+        let mut min = Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let x = if i == 0 {bbox.x.min} else {bbox.x.max};
+                    let y = if j == 0 {bbox.y.min} else {bbox.y.max};
+                    let z = if k == 0 {bbox.z.min} else {bbox.z.max};
+                    let corner = matrix.transform_point(&Vector3::new(x, y, z).into()).coords;
+                    min = min.inf(&corner);
+                    max = max.sup(&corner);
+                }
+            }
+        }
 
-        let mut normal = rec.normal.clone();
-        normal.x = self.cos_theta * rec.normal.x + self.sin_theta * rec.normal.z;
-        normal.z = -self.sin_theta * rec.normal.x + self.cos_theta * rec.normal.z;
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            linear,
+            inverse_linear,
+            abs_det:linear.determinant().abs(),
+            bbox:Aabb::initial(&min, &max),
+        })
+    }
+
+    pub fn translation(object:Arc<dyn Hittable>, offset:Vector3<f64>) -> Self {
+        Self::new(object, Matrix4::new_translation(&offset)).unwrap()
+    }
 
-        rec.p = p;
-        rec.normal = normal;
+    pub fn rotation(object:Arc<dyn Hittable>, axis:Vector3<f64>, angle:f64) -> Self {
+        let matrix = Matrix4::from_axis_angle(&Unit::new_normalize(axis), angle.to_radians());
+        Self::new(object, matrix).unwrap()
+    }
+
+    pub fn scaling(object:Arc<dyn Hittable>, scale:Vector3<f64>) -> Option<Self> {
+        Self::new(object, Matrix4::new_nonuniform_scaling(&scale))
+    }
+
+    pub fn matrix(&self) -> &Matrix4<f64> {
+        &self.matrix
+    }
 
+    fn point_to_object(&self, p:&Vector3<f64>) -> Vector3<f64> {
+        self.inverse.transform_point(&(*p).into()).coords
+    }
+}
+
+impl Hittable for Transform {
+    fn hit (&self, r: &Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        let origin = self.point_to_object(&r.origin());
+        let direction = self.inverse_linear * r.direction();
+        let object_r = Ray::initial(origin, direction, r.time());
+
+        if !self.object.hit(&object_r, ray_t, rec) {
+            return false;
+        }
+        rec.p = self.matrix.transform_point(&rec.p.into()).coords;
+        rec.normal = (self.inverse_linear.transpose() * rec.normal).normalize();
+        rec.dpdu = self.linear * rec.dpdu;
+        rec.dpdv = self.linear * rec.dpdv;
         true
     }
 
     fn bounding_box(&self) -> Aabb {
         self.bbox.clone()
     }
+
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        let local_direction = (self.inverse_linear * direction).normalize();
+        let pdf = self.object.pdf_value(&self.point_to_object(origin), &local_direction);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+        pdf * (self.linear * local_direction).norm().powi(3) / self.abs_det
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        self.linear * self.object.random(&self.point_to_object(origin))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let mut bounds = self.object.light_bounds()?;
+        bounds.bounds = self.bbox.clone();
+        bounds.phi *= self.abs_det.powf(2.0 / 3.0);
+        bounds.w = (self.inverse_linear.transpose() * bounds.w).normalize();
+        if bounds.cos_theta_o < 1.0 {
+            bounds.cos_theta_o = -1.0;
+        }
+        Some(bounds)
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = Vec::new();
+        self.object.clone().collect_emitters(&mut inner);
+        for object in inner {
+            if let Some(transform) = Transform::new(object, self.matrix) {
+                emitters.push(Arc::new(transform));
+            }
+        }
+    }
 }
\ No newline at end of file
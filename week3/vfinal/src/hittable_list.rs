@@ -6,6 +6,7 @@ use crate::ray::Ray;
 use crate::interval::Interval;
 use crate::aabb::Aabb;
 use crate::rtweekend::random_int;
+use crate::light_sampler::LightBounds;
 
 #[derive(Clone)]
 pub struct HittableList {
@@ -38,6 +39,16 @@ impl HittableList {
         self.objects.push(object);
     }
 
+    pub fn emitters(world:&Arc<dyn Hittable>) -> Self {
+        let mut emitters = Vec::new();
+        world.clone().collect_emitters(&mut emitters);
+        let mut list = HittableList::new();
+        for object in emitters {
+            list.add(object);
+        }
+        list
+    }
+
 }
 
 impl Hittable for HittableList {
@@ -59,6 +70,9 @@ impl Hittable for HittableList {
     }
     
     fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
         let weight = 1.0 / self.objects.len() as f64;
         let mut sum = 0.0;
 
@@ -70,7 +84,21 @@ impl Hittable for HittableList {
     }
     
     fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        if self.objects.is_empty() {
+            return Vector3::new(1.0,0.0,0.0);
+        }
         let int_size = self.objects.len() as i64;
         self.objects[random_int(0, int_size - 1) as usize].random(&origin)
     }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let bounds = self.objects.iter().map(|object| object.light_bounds()).collect::<Option<Vec<_>>>()?;
+        bounds.into_iter().reduce(|a, b| LightBounds::merge(&a, &b))
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        for object in self.objects.iter() {
+            object.clone().collect_emitters(emitters);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,460 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::distribution::Distribution2D;
+use crate::emission::EmissionProfile;
+
+const SAMPLE_PHI:usize = 256;
+const SAMPLE_THETA:usize = 128;
+
+pub struct IesProfile {
+    pub vertical_angles:Vec<f64>,
+    pub horizontal_angles:Vec<f64>,
+    pub candela:Vec<Vec<f64>>,
+    pub lumens:Option<f64>,
+    max_candela:f64,
+    flux:f64,
+    integral:f64,
+    distribution:Distribution2D,
+}
+
+struct Tokens<'a> {
+    values:Vec<&'a str>,
+    next:usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new (source:&'a str) -> Self {
+        Tokens {
+            values:source.split(|c:char| c.is_whitespace() || c == ',').filter(|s| !s.is_empty()).collect(),
+            next:0,
+        }
+    }
+
+    fn number(&mut self, what:&str) -> Result<f64, String> {
+        let token = self.values.get(self.next).ok_or_else(|| format!("unexpected end of file while reading {}", what))?;
+        self.next += 1;
+        token.parse::<f64>().map_err(|_| format!("invalid number '{}' while reading {}", token, what))
+    }
+
+    fn count(&mut self, what:&str) -> Result<usize, String> {
+        let value = self.number(what)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(format!("{} must be a non-negative integer, got {}", what, value));
+        }
+        Ok(value as usize)
+    }
+
+    fn numbers(&mut self, n:usize, what:&str) -> Result<Vec<f64>, String> {
+        (0..n).map(|_| self.number(what)).collect()
+    }
+}
+
+fn check_ascending(angles:&[f64], what:&str) -> Result<(), String> {
+    if angles.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err(format!("{} angles must be strictly increasing", what));
+    }
+    Ok(())
+}
+
+fn interval(angles:&[f64], x:f64) -> (usize, f64) {
+    if angles.len() == 1 {
+        return (0, 0.0);
+    }
+    let i = angles.partition_point(|&a| a <= x).clamp(1, angles.len() - 1) - 1;
+    let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+    (i, t.clamp(0.0, 1.0))
+}
+
+fn to_angles(w:&Vector3<f64>) -> (f64, f64) {
+    let w = w.normalize();
+    let theta = w.z.clamp(-1.0, 1.0).acos().to_degrees();
+    let phi = w.y.atan2(w.x).to_degrees().rem_euclid(360.0);
+    (theta, phi)
+}
+
+impl IesProfile {
+    pub fn load(path:&Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(source:&str) -> Result<Self, String> {
+        let mut rest = source;
+        let tilt = loop {
+            let (line, tail) = match rest.split_once('\n') {
+                Some((line, tail)) => (line, tail),
+                None if rest.is_empty() => return Err("missing TILT line".to_string()),
+                None => (rest, ""),
+            };
+            rest = tail;
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.to_ascii_uppercase().starts_with("TILT") {
+                match line.split_once('=') {
+                    Some((_, tilt)) => break tilt.trim().to_string(),
+                    None => return Err(format!("malformed TILT line '{}'", line)),
+                }
+            }
+        };
+
+        let mut tokens = Tokens::new(rest);
+        match tilt.to_ascii_uppercase().as_str() {
+            "NONE" => {}
+            "INCLUDE" => {
+                tokens.number("lamp-to-luminaire geometry")?;
+                let pairs = tokens.count("number of tilt angles")?;
+                tokens.numbers(2 * pairs, "tilt data")?;
+            }
+            _ => return Err(format!("external TILT file '{}' is not supported", tilt)),
+        }
+
+        let lamps = tokens.number("number of lamps")?;
+        let lumens_per_lamp = tokens.number("lumens per lamp")?;
+        let multiplier = tokens.number("candela multiplier")?;
+        let n_vertical = tokens.count("number of vertical angles")?;
+        let n_horizontal = tokens.count("number of horizontal angles")?;
+        let photometric_type = tokens.number("photometric type")?;
+        tokens.numbers(4, "luminaire dimensions")?;
+        let ballast_factor = tokens.number("ballast factor")?;
+        tokens.numbers(2, "input watts")?;
+
+        if photometric_type != 1.0 {
+            return Err(format!("only type C photometry is supported, got type {}", photometric_type));
+        }
+        if n_vertical == 0 || n_horizontal == 0 {
+            return Err("the candela table must not be empty".to_string());
+        }
+
+        let vertical_angles = tokens.numbers(n_vertical, "vertical angles")?;
+        let horizontal_angles = tokens.numbers(n_horizontal, "horizontal angles")?;
+        check_ascending(&vertical_angles, "vertical")?;
+        check_ascending(&horizontal_angles, "horizontal")?;
+
+        let first_h = horizontal_angles[0];
+        let last_h = horizontal_angles[n_horizontal - 1];
+        let supported = match n_horizontal {
+            1 => true,
+            _ => (first_h == 0.0 && [90.0, 180.0, 360.0].contains(&last_h)) || (first_h == 90.0 && last_h == 270.0),
+        };
+        if !supported {
+            return Err(format!("unsupported horizontal angle range {} to {}", first_h, last_h));
+        }
+        if vertical_angles[0] < 0.0 || vertical_angles[n_vertical - 1] > 180.0 {
+            return Err("vertical angles must lie between 0 and 180 degrees".to_string());
+        }
+
+        let scale = multiplier * ballast_factor;
+        let mut candela = Vec::with_capacity(n_horizontal);
+        for _ in 0..n_horizontal {
+            let row = tokens.numbers(n_vertical, "candela values")?;
+            candela.push(row.into_iter().map(|c| (c * scale).max(0.0)).collect::<Vec<f64>>());
+        }
+
+        let max_candela = candela.iter().flatten().fold(0.0, |a:f64, &b| a.max(b));
+        if max_candela <= 0.0 {
+            return Err("the profile has no positive candela values".to_string());
+        }
+
+        let mut profile = IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            lumens:if lumens_per_lamp > 0.0 {Some(lamps * lumens_per_lamp)} else {None},
+            max_candela,
+            flux:0.0,
+            integral:0.0,
+            distribution:Distribution2D::new(&[0.0], 1, 1),
+        };
+        profile.build_tables();
+        Ok(profile)
+    }
+
+    fn build_tables(&mut self) {
+        let mut func = vec![0.0; SAMPLE_PHI * SAMPLE_THETA];
+        let mut integral = 0.0;
+        for j in 0..SAMPLE_THETA {
+            let theta = PI * (j as f64 + 0.5) / SAMPLE_THETA as f64;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for i in 0..SAMPLE_PHI {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / SAMPLE_PHI as f64;
+                let c = self.candela(theta.to_degrees(), phi.to_degrees());
+                func[j * SAMPLE_PHI + i] = c * sin_theta;
+                if cos_theta > 0.0 {
+                    integral += c * sin_theta * cos_theta;
+                }
+            }
+        }
+        let cell = (PI / SAMPLE_THETA as f64) * (2.0 * PI / SAMPLE_PHI as f64);
+        self.flux = func.iter().sum::<f64>() * cell;
+        self.integral = integral * cell / self.max_candela;
+        self.distribution = Distribution2D::new(&func, SAMPLE_PHI, SAMPLE_THETA);
+    }
+
+    fn fold_phi(&self, phi:f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let mut phi = phi.rem_euclid(360.0);
+        if self.horizontal_angles.len() == 1 {
+            return first;
+        }
+        if first == 90.0 {
+            if phi < 90.0 {
+                phi = 180.0 - phi;
+            } else if phi > 270.0 {
+                phi = 540.0 - phi;
+            }
+            return phi;
+        }
+        if last <= 180.0 && phi > 180.0 {
+            phi = 360.0 - phi;
+        }
+        if last <= 90.0 && phi > 90.0 {
+            phi = 180.0 - phi;
+        }
+        phi
+    }
+
+    pub fn candela(&self, theta:f64, phi:f64) -> f64 {
+        let vertical = &self.vertical_angles;
+        if theta < vertical[0] || theta > vertical[vertical.len() - 1] {
+            return 0.0;
+        }
+        let (iv, tv) = interval(vertical, theta);
+        let (ih, th) = interval(&self.horizontal_angles, self.fold_phi(phi));
+        let along = |row:&Vec<f64>| {
+            if tv == 0.0 {row[iv]} else {row[iv] * (1.0 - tv) + row[iv + 1] * tv}
+        };
+        let c0 = along(&self.candela[ih]);
+        if th == 0.0 {
+            return c0;
+        }
+        c0 * (1.0 - th) + along(&self.candela[ih + 1]) * th
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    pub fn flux(&self) -> f64 {
+        self.flux
+    }
+}
+
+impl EmissionProfile for IesProfile {
+    fn value(&self, w:&Vector3<f64>) -> f64 {
+        let (theta, phi) = to_angles(w);
+        self.candela(theta, phi) / self.max_candela
+    }
+
+    fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    fn sample(&self, u0:f64, u1:f64) -> (Vector3<f64>, f64) {
+        let (u, v, pdf) = self.distribution.sample_continuous(u0, u1);
+        let theta = v * PI;
+        let phi = u * 2.0 * PI;
+        let sin_theta = theta.sin();
+        let direction = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), theta.cos());
+        if sin_theta == 0.0 {
+            return (direction, 0.0);
+        }
+        (direction, pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    fn pdf(&self, w:&Vector3<f64>) -> f64 {
+        let (theta, phi) = to_angles(w);
+        let sin_theta = theta.to_radians().sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(phi / 360.0, theta / 180.0) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_file(name:&str) -> IesProfile {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/ies").join(name);
+        IesProfile::load(&path).unwrap()
+    }
+
+    fn close(a:f64, b:f64, tolerance:f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn isotropic_profile_is_uniform() {
+        let ies = sample_file("isotropic.ies");
+        assert_eq!(ies.vertical_angles, vec![0.0, 45.0, 90.0, 135.0, 180.0]);
+        assert_eq!(ies.horizontal_angles, vec![0.0]);
+        assert_eq!(ies.lumens, None);
+        for (theta, phi) in [(0.0, 0.0), (30.0, 75.0), (90.0, 200.0), (170.0, 359.0)] {
+            assert_eq!(ies.candela(theta, phi), 100.0);
+        }
+        assert!(close(ies.flux(), 400.0 * PI, 1e-3));
+        assert!(close(ies.integral(), PI, 1e-3));
+    }
+
+    #[test]
+    fn candela_multiplier_is_applied() {
+        let ies = sample_file("downlight.ies");
+        assert_eq!(ies.lumens, Some(1000.0));
+        assert_eq!(ies.max_candela(), 500.0);
+        assert_eq!(ies.candela(60.0, 0.0), 125.0);
+        assert_eq!(ies.candela(60.0, 123.0), 125.0);
+    }
+
+    #[test]
+    fn downlight_emits_nothing_upwards() {
+        let ies = sample_file("downlight.ies");
+        assert_eq!(ies.candela(90.0, 0.0), 0.0);
+        assert_eq!(ies.candela(120.0, 45.0), 0.0);
+        assert_eq!(ies.value(&Vector3::new(0.0, 0.0, -1.0)), 0.0);
+        assert_eq!(ies.value(&Vector3::new(0.0, 0.0, 1.0)), 1.0);
+    }
+
+    #[test]
+    fn downlight_flux_matches_cosine_squared() {
+        let ies = sample_file("downlight.ies");
+        assert!(close(ies.flux(), 500.0 * 2.0 * PI / 3.0, 0.01));
+        assert!(close(ies.integral(), PI / 2.0, 0.01));
+    }
+
+    #[test]
+    fn vertical_angles_are_interpolated() {
+        let ies = sample_file("downlight.ies");
+        let expected = 0.5 * (ies.candela(10.0, 0.0) + ies.candela(20.0, 0.0));
+        assert!(close(ies.candela(15.0, 0.0), expected, 1e-12));
+    }
+
+    #[test]
+    fn quadrant_symmetry_folds_horizontal_angles() {
+        let ies = sample_file("quadrant.ies");
+        assert_eq!(ies.candela(30.0, 0.0), 250.0);
+        assert_eq!(ies.candela(30.0, 180.0), 250.0);
+        assert_eq!(ies.candela(30.0, 135.0), 180.0);
+        assert_eq!(ies.candela(30.0, 225.0), 180.0);
+        assert_eq!(ies.candela(30.0, 270.0), 90.0);
+        assert_eq!(ies.candela(30.0, 315.0), 180.0);
+    }
+
+    #[test]
+    fn horizontal_angles_are_interpolated() {
+        let ies = sample_file("quadrant.ies");
+        assert!(close(ies.candela(15.0, 22.5), 257.5, 1e-12));
+        assert!(close(ies.candela(15.0, 337.5), 257.5, 1e-12));
+    }
+
+    #[test]
+    fn bilateral_profile_with_included_tilt() {
+        let ies = sample_file("bilateral_tilt.ies");
+        assert_eq!(ies.horizontal_angles, vec![0.0, 90.0, 180.0]);
+        assert_eq!(ies.candela(45.0, 90.0), 25.0);
+        assert_eq!(ies.candela(45.0, 270.0), 25.0);
+        assert_eq!(ies.candela(180.0, 0.0), 5.0);
+        assert_eq!(ies.candela(0.0, 180.0), 40.0);
+        assert!(ies.value(&Vector3::new(0.0, 0.0, -1.0)) > 0.0);
+    }
+
+    #[test]
+    fn legacy_file_without_version_line() {
+        let ies = sample_file("lm63_1986.ies");
+        assert_eq!(ies.lumens, Some(1500.0));
+        assert_eq!(ies.candela(0.0, 0.0), 400.0);
+        assert_eq!(ies.candela(45.0, 0.0), 300.0);
+        assert_eq!(ies.candela(100.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn sampling_pdf_integrates_to_one() {
+        let ies = sample_file("quadrant.ies");
+        let (n_theta, n_phi) = (200, 400);
+        let mut total = 0.0;
+        for j in 0..n_theta {
+            let theta = PI * (j as f64 + 0.5) / n_theta as f64;
+            for i in 0..n_phi {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / n_phi as f64;
+                let w = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                total += ies.pdf(&w) * theta.sin();
+            }
+        }
+        total *= (PI / n_theta as f64) * (2.0 * PI / n_phi as f64);
+        assert!(close(total, 1.0, 1e-3));
+    }
+
+    #[test]
+    fn samples_follow_the_candela_distribution() {
+        let ies = sample_file("bilateral_tilt.ies");
+        let n = 128;
+        let mut estimate = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                let (w, pdf) = ies.sample((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                assert!(pdf > 0.0);
+                assert!(close(pdf, ies.pdf(&w), 1e-6));
+                let (theta, phi) = to_angles(&w);
+                estimate += ies.candela(theta, phi) / pdf;
+            }
+        }
+        estimate /= (n * n) as f64;
+        assert!(close(estimate, ies.flux(), 0.01));
+    }
+
+    #[test]
+    fn sampling_avoids_dark_directions() {
+        let ies = sample_file("downlight.ies");
+        for j in 0..32 {
+            for i in 0..32 {
+                let (w, _) = ies.sample((i as f64 + 0.5) / 32.0, (j as f64 + 0.5) / 32.0);
+                assert!(w.z > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_missing_tilt() {
+        let err = IesProfile::parse("IESNA:LM-63-2002\n[TEST] no tilt\n").err().unwrap();
+        assert!(err.contains("TILT"));
+    }
+
+    #[test]
+    fn rejects_external_tilt_file() {
+        let err = IesProfile::parse("TILT=lamp.tlt\n1 1000 1 1 1 1 1 0 0 0\n1 1 0\n0\n0\n10\n").err().unwrap();
+        assert!(err.contains("not supported"));
+    }
+
+    #[test]
+    fn rejects_type_b_photometry() {
+        let err = IesProfile::parse("TILT=NONE\n1 1000 1 1 1 2 1 0 0 0\n1 1 0\n0\n0\n10\n").err().unwrap();
+        assert!(err.contains("type C"));
+    }
+
+    #[test]
+    fn rejects_truncated_candela_table() {
+        let err = IesProfile::parse("TILT=NONE\n1 1000 1 3 1 1 1 0 0 0\n1 1 0\n0 45 90\n0\n10 5\n").err().unwrap();
+        assert!(err.contains("end of file"));
+    }
+
+    #[test]
+    fn rejects_descending_angles() {
+        let err = IesProfile::parse("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 0\n90 0\n0\n10 5\n").err().unwrap();
+        assert!(err.contains("increasing"));
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        let err = IesProfile::parse("TILT=NONE\n1 1000 one 1 1 1 1 0 0 0\n").err().unwrap();
+        assert!(err.contains("'one'"));
+    }
+
+    #[test]
+    fn rejects_dark_profiles() {
+        let err = IesProfile::parse("TILT=NONE\n1 1000 1 2 1 1 1 0 0 0\n1 1 0\n0 90\n0\n0 0\n").err().unwrap();
+        assert!(err.contains("positive"));
+    }
+}
@@ -0,0 +1,130 @@
+use image::error::{ImageFormatHint, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind};
+use image::{ImageBuffer, ImageError, ImageFormat, ImageResult, Rgb, Rgb32FImage, RgbImage};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::color::{sanitize, to_rgb16, to_rgb8};
+use crate::framebuffer::Framebuffer;
+
+pub trait ImageWriter {
+    fn write(&self, fb:&Framebuffer, path:&Path) -> ImageResult<()>;
+}
+
+pub struct PpmWriter {}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, fb:&Framebuffer, path:&Path) -> ImageResult<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "P6\n{} {}\n255\n", fb.width, fb.height)?;
+        for pixel in fb.pixels.iter() {
+            out.write_all(&to_rgb8(pixel))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+pub struct PngWriter {
+    pub bit_depth:i64,
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, fb:&Framebuffer, path:&Path) -> ImageResult<()> {
+        if self.bit_depth == 16 {
+            let data:Vec<u16> = fb.pixels.iter().flat_map(to_rgb16).collect();
+            let img:ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_raw(fb.width as u32, fb.height as u32, data).unwrap();
+            img.save_with_format(path, ImageFormat::Png)
+        }
+        else {
+            let data:Vec<u8> = fb.pixels.iter().flat_map(to_rgb8).collect();
+            let img = RgbImage::from_raw(fb.width as u32, fb.height as u32, data).unwrap();
+            img.save_with_format(path, ImageFormat::Png)
+        }
+    }
+}
+
+pub struct HdrWriter {
+    pub format:ImageFormat,
+}
+
+impl ImageWriter for HdrWriter {
+    fn write(&self, fb:&Framebuffer, path:&Path) -> ImageResult<()> {
+        let data:Vec<f32> = fb.pixels.iter()
+            .flat_map(|p| {
+                let c = sanitize(p);
+                [c.x as f32, c.y as f32, c.z as f32]
+            })
+            .collect();
+        let img = Rgb32FImage::from_raw(fb.width as u32, fb.height as u32, data).unwrap();
+        img.save_with_format(path, self.format)
+    }
+}
+
+pub fn writer_for_path(path:&Path, bit_depth:i64) -> ImageResult<Box<dyn ImageWriter>> {
+    if bit_depth != 8 && bit_depth != 16 {
+        let message = format!("bit depth must be 8 or 16, got {}", bit_depth);
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(message))));
+    }
+    let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("ppm") => Ok(Box::new(PpmWriter {})),
+        Some("png") => Ok(Box::new(PngWriter { bit_depth })),
+        Some("exr") => Ok(Box::new(HdrWriter { format:ImageFormat::OpenExr })),
+        Some("hdr") => Ok(Box::new(HdrWriter { format:ImageFormat::Hdr })),
+        _ => Err(unsupported_format(path)),
+    }
+}
+
+fn unsupported_format(path:&Path) -> ImageError {
+    let hint = ImageFormatHint::PathExtension(path.to_path_buf());
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(hint.clone(), UnsupportedErrorKind::Format(hint)))
+}
+
+pub fn check_output(filename:&str, bit_depth:i64) -> ImageResult<()> {
+    let path = Path::new(filename);
+    writer_for_path(path, bit_depth)?;
+    if path.exists() {
+        OpenOptions::new().write(true).open(path)?;
+    } else {
+        File::create(path)?;
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+pub fn save_image(fb:&Framebuffer, filename:&str, bit_depth:i64) -> ImageResult<()> {
+    let path = Path::new(filename);
+    writer_for_path(path, bit_depth)?.write(fb, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn rejects_unsupported_bit_depths() {
+        for bit_depth in [1, 12, 32] {
+            assert!(writer_for_path(Path::new("out.png"), bit_depth).is_err(), "{}", bit_depth);
+            assert!(check_output("out.png", bit_depth).is_err(), "{}", bit_depth);
+        }
+        assert!(writer_for_path(Path::new("out.png"), 16).is_ok());
+        assert!(writer_for_path(Path::new("out.txt"), 8).is_err());
+    }
+
+    #[test]
+    fn non_finite_pixels_are_written_black() {
+        let mut fb = Framebuffer::new(3, 1);
+        fb.pixels = vec![Vector3::new(f64::INFINITY, 0.5, f64::NAN), Vector3::repeat(f64::NEG_INFINITY), Vector3::repeat(1.0)];
+        let path = std::env::temp_dir().join(format!("light-writer-{}.ppm", std::process::id()));
+        save_image(&fb, &path.to_string_lossy(), 8).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let pixels = &bytes[bytes.len() - 9..];
+        assert_eq!(pixels[0], 0);
+        assert_eq!(pixels[2], 0);
+        assert_eq!(&pixels[3..6], &[0, 0, 0]);
+        assert_eq!(&pixels[6..9], &[255, 255, 255]);
+    }
+}
@@ -0,0 +1,269 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::emission::EmissionProfile;
+use crate::onb::Onb;
+
+pub struct LightSample {
+    pub direction:Vector3<f64>,
+    pub distance:f64,
+    pub radiance:Vector3<f64>,
+}
+
+pub struct EmittedRay {
+    pub origin:Vector3<f64>,
+    pub direction:Vector3<f64>,
+    pub intensity:Vector3<f64>,
+    pub pdf:f64,
+}
+
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p:&Vector3<f64>) -> Option<LightSample>;
+
+    // Lights without a position, like a directional light, cannot start a ray.
+    fn sample_le(&self, _u0:f64, _u1:f64) -> Option<EmittedRay> {
+        None
+    }
+}
+
+fn profile_value(profile:&Option<Arc<dyn EmissionProfile>>, frame:&Onb, direction:&Vector3<f64>) -> f64 {
+    match profile {
+        Some(profile) => profile.value(&frame.to_local(&-direction)),
+        None => 1.0,
+    }
+}
+
+// Emission directions follow the profile when there is one, otherwise they are
+// spread uniformly over the cone around the frame axis down to cos_max.
+fn sample_emission(profile:&Option<Arc<dyn EmissionProfile>>, frame:&Onb, cos_max:f64, u0:f64, u1:f64) -> Option<(Vector3<f64>, f64, f64)> {
+    let (local, pdf, scale) = match profile {
+        Some(profile) => {
+            let (local, pdf) = profile.sample(u0, u1);
+            (local, pdf, profile.value(&local))
+        }
+        None => {
+            let cos_theta = 1.0 - u0 * (1.0 - cos_max);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u1;
+            (Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta), 1.0 / (2.0 * PI * (1.0 - cos_max)), 1.0)
+        }
+    };
+    if pdf <= 0.0 {
+        return None;
+    }
+    Some((frame.local_vec(&local), pdf, scale))
+}
+
+pub struct PointLight {
+    position:Vector3<f64>,
+    intensity:Vector3<f64>,
+    profile:Option<Arc<dyn EmissionProfile>>,
+    frame:Onb,
+}
+
+impl PointLight {
+    pub fn new (position:Vector3<f64>, intensity:Vector3<f64>) -> Self {
+        let mut frame = Onb::new();
+        frame.build_from_w(&Vector3::new(0.0, -1.0, 0.0));
+        PointLight {
+            position,
+            intensity,
+            profile:None,
+            frame,
+        }
+    }
+
+    pub fn set_profile(&mut self, profile:Arc<dyn EmissionProfile>, axis:Vector3<f64>) {
+        self.frame.build_from_w(&axis);
+        self.profile = Some(profile);
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p:&Vector3<f64>) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.norm_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let scale = profile_value(&self.profile, &self.frame, &direction);
+        if scale == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance:self.intensity * scale / distance_squared,
+        })
+    }
+
+    fn sample_le(&self, u0:f64, u1:f64) -> Option<EmittedRay> {
+        let (direction, pdf, scale) = sample_emission(&self.profile, &self.frame, -1.0, u0, u1)?;
+        Some(EmittedRay {
+            origin:self.position,
+            direction,
+            intensity:self.intensity * scale,
+            pdf,
+        })
+    }
+}
+
+pub struct SpotLight {
+    position:Vector3<f64>,
+    direction:Vector3<f64>,
+    intensity:Vector3<f64>,
+    cos_falloff_start:f64,
+    cos_falloff_end:f64,
+    profile:Option<Arc<dyn EmissionProfile>>,
+    frame:Onb,
+}
+
+impl SpotLight {
+    pub fn new (position:Vector3<f64>, direction:Vector3<f64>, intensity:Vector3<f64>, angle:f64, falloff_start:f64) -> Self {
+        let mut frame = Onb::new();
+        frame.build_from_w(&direction);
+        SpotLight {
+            position,
+            direction:direction.normalize(),
+            intensity,
+            cos_falloff_start:falloff_start.min(angle).to_radians().cos(),
+            cos_falloff_end:angle.to_radians().cos(),
+            profile:None,
+            frame,
+        }
+    }
+
+    pub fn set_profile(&mut self, profile:Arc<dyn EmissionProfile>) {
+        self.profile = Some(profile);
+    }
+
+    fn falloff(&self, cos_theta:f64) -> f64 {
+        if cos_theta <= self.cos_falloff_end {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        let t = (cos_theta - self.cos_falloff_end) / (self.cos_falloff_start - self.cos_falloff_end);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p:&Vector3<f64>) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.norm_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(&self.direction)) * profile_value(&self.profile, &self.frame, &direction);
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            radiance:self.intensity * falloff / distance_squared,
+        })
+    }
+
+    fn sample_le(&self, u0:f64, u1:f64) -> Option<EmittedRay> {
+        let (direction, pdf, scale) = sample_emission(&self.profile, &self.frame, self.cos_falloff_end, u0, u1)?;
+        Some(EmittedRay {
+            origin:self.position,
+            direction,
+            intensity:self.intensity * scale * self.falloff(direction.dot(&self.direction)),
+            pdf,
+        })
+    }
+}
+
+pub struct DirectionalLight {
+    direction:Vector3<f64>,
+    irradiance:Vector3<f64>,
+}
+
+impl DirectionalLight {
+    pub fn new (direction:Vector3<f64>, irradiance:Vector3<f64>) -> Self {
+        DirectionalLight {
+            direction:direction.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p:&Vector3<f64>) -> Option<LightSample> {
+        Some(LightSample {
+            direction:-self.direction,
+            distance:f64::INFINITY,
+            radiance:self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emission::CosinePower;
+    use crate::ies::IesProfile;
+    use std::path::Path;
+
+    fn emitted_flux(light:&dyn Light) -> f64 {
+        let n = 256;
+        let mut flux = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                if let Some(ray) = light.sample_le((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64) {
+                    assert!((ray.direction.norm() - 1.0).abs() < 1e-9);
+                    flux += ray.intensity.x / ray.pdf;
+                }
+            }
+        }
+        flux / (n * n) as f64
+    }
+
+    fn close(a:f64, b:f64, tolerance:f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs()
+    }
+
+    #[test]
+    fn point_light_emits_its_flux() {
+        let light = PointLight::new(Vector3::zeros(), Vector3::new(2.0, 2.0, 2.0));
+        assert!(close(emitted_flux(&light), 8.0 * PI, 1e-9));
+
+        let mut shaped = PointLight::new(Vector3::zeros(), Vector3::new(2.0, 2.0, 2.0));
+        shaped.set_profile(Arc::new(CosinePower::new(3.0)), Vector3::new(0.0, 0.0, 1.0));
+        assert!(close(emitted_flux(&shaped), 2.0 * 2.0 * PI / 4.0, 1e-9));
+        let ray = shaped.sample_le(0.3, 0.7).unwrap();
+        assert!(ray.direction.z > 0.0);
+    }
+
+    #[test]
+    fn ies_point_light_samples_the_candela_distribution() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/ies/downlight.ies");
+        let ies = Arc::new(IesProfile::load(&path).unwrap());
+        let mut light = PointLight::new(Vector3::zeros(), Vector3::new(1.0, 1.0, 1.0));
+        light.set_profile(ies.clone(), Vector3::new(0.0, -1.0, 0.0));
+        for (u0, u1) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            assert!(light.sample_le(u0, u1).unwrap().direction.y < 0.0);
+        }
+        assert!(close(emitted_flux(&light), ies.flux() / ies.max_candela(), 1e-2));
+    }
+
+    #[test]
+    fn spot_light_emits_inside_its_cone() {
+        let light = SpotLight::new(Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0), Vector3::new(3.0, 3.0, 3.0), 30.0, 30.0);
+        let cos_angle = 30f64.to_radians().cos();
+        for (u0, u1) in [(0.0, 0.0), (0.4, 0.9), (1.0, 0.5)] {
+            let ray = light.sample_le(u0, u1).unwrap();
+            assert!(ray.direction.x >= cos_angle - 1e-9);
+        }
+        assert!(close(emitted_flux(&light), 3.0 * 2.0 * PI * (1.0 - cos_angle), 1e-9));
+    }
+}
@@ -0,0 +1,591 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::distribution::Distribution1D;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::random_double;
+
+const ONE_MINUS_EPSILON:f64 = 1.0 - f64::EPSILON;
+const BUCKETS:usize = 12;
+
+#[derive(Clone)]
+pub struct LightBounds {
+    pub bounds:Aabb,
+    pub phi:f64,
+    pub w:Vector3<f64>,
+    pub cos_theta_o:f64,
+    pub cos_theta_e:f64,
+    pub two_sided:bool,
+}
+
+fn safe_sqrt(x:f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+pub fn luminance(c:&Vector3<f64>) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn rotate(v:&Vector3<f64>, axis:&Vector3<f64>, angle:f64) -> Vector3<f64> {
+    let k = axis.normalize();
+    let (sin, cos) = angle.sin_cos();
+    v * cos + k.cross(v) * sin + k * k.dot(v) * (1.0 - cos)
+}
+
+fn cone_union(wa:&Vector3<f64>, cos_a:f64, wb:&Vector3<f64>, cos_b:f64) -> (Vector3<f64>, f64) {
+    let theta_a = cos_a.clamp(-1.0, 1.0).acos();
+    let theta_b = cos_b.clamp(-1.0, 1.0).acos();
+    let theta_d = wa.dot(wb).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return (*wa, cos_a);
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return (*wb, cos_b);
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return (*wa, -1.0);
+    }
+    let wr = wa.cross(wb);
+    if wr.norm_squared() == 0.0 {
+        return (*wa, -1.0);
+    }
+    (rotate(wa, &wr, theta_o - theta_a), theta_o.cos())
+}
+
+fn cos_sub_clamped(sin_a:f64, cos_a:f64, sin_b:f64, cos_b:f64) -> f64 {
+    if cos_a > cos_b {1.0} else {cos_a * cos_b + sin_a * sin_b}
+}
+
+fn sin_sub_clamped(sin_a:f64, cos_a:f64, sin_b:f64, cos_b:f64) -> f64 {
+    if cos_a > cos_b {0.0} else {sin_a * cos_b - cos_a * sin_b}
+}
+
+pub fn emitted_power(mat:&Arc<dyn Material>, p:&Vector3<f64>, normal:&Vector3<f64>, u:f64, v:f64, area:f64) -> f64 {
+    let mut rec = HitRecord::new();
+    rec.p = *p;
+    rec.normal = *normal;
+    rec.mat = mat.clone();
+    rec.u = u;
+    rec.v = v;
+    rec.front_face = true;
+    luminance(&mat.radiant_exitance(&rec)) * area
+}
+
+impl LightBounds {
+    pub fn new (bounds:Aabb, phi:f64, w:Vector3<f64>, cos_theta_o:f64, cos_theta_e:f64, two_sided:bool) -> Self {
+        LightBounds {
+            bounds,
+            phi,
+            w:w.normalize(),
+            cos_theta_o,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    pub fn omnidirectional (bounds:Aabb, phi:f64) -> Self {
+        Self::new(bounds, phi, Vector3::new(0.0,0.0,1.0), -1.0, 0.0, true)
+    }
+
+    pub fn merge(a:&LightBounds, b:&LightBounds) -> Self {
+        let (w, cos_theta_o) = cone_union(&a.w, a.cos_theta_o, &b.w, b.cos_theta_o);
+        LightBounds {
+            bounds:Aabb::merge(&a.bounds, &b.bounds),
+            phi:a.phi + b.phi,
+            w,
+            cos_theta_o,
+            cos_theta_e:a.cos_theta_e.min(b.cos_theta_e),
+            two_sided:a.two_sided || b.two_sided,
+        }
+    }
+
+    fn diagonal(&self) -> Vector3<f64> {
+        Vector3::new(self.bounds.x.size(), self.bounds.y.size(), self.bounds.z.size())
+    }
+
+    fn contains(&self, p:&Vector3<f64>) -> bool {
+        self.bounds.x.contains(p.x) && self.bounds.y.contains(p.y) && self.bounds.z.contains(p.z)
+    }
+
+    pub fn importance(&self, p:&Vector3<f64>) -> f64 {
+        if self.phi <= 0.0 {
+            return 0.0;
+        }
+        let pc = self.bounds.centroid();
+        let radius = self.diagonal().norm() / 2.0;
+        let d2 = (p - pc).norm_squared().max(radius);
+
+        let wi = (p - pc).normalize();
+        let mut cos_theta_w = self.w.dot(&wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        if cos_theta_w.is_nan() {
+            cos_theta_w = 1.0;
+        }
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+
+        let center_distance2 = (p - pc).norm_squared();
+        let cos_theta_b = if self.contains(p) || center_distance2 < radius * radius {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - radius * radius / center_distance2)
+        };
+        let sin_theta_b = safe_sqrt(1.0 - cos_theta_b * cos_theta_b);
+
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+        self.phi * cos_theta_p / d2
+    }
+}
+
+pub trait LightSampler: Send + Sync {
+    fn sample(&self, p:&Vector3<f64>, u:f64) -> Option<(usize, f64)>;
+    fn pmf(&self, p:&Vector3<f64>, index:usize) -> f64;
+
+    fn pdf_value(&self, lights:&[Arc<dyn Hittable>], origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        let mut sum = 0.0;
+        for (index, light) in lights.iter().enumerate() {
+            let pmf = self.pmf(origin, index);
+            if pmf > 0.0 {
+                sum += pmf * light.pdf_value(origin, direction);
+            }
+        }
+        sum
+    }
+}
+
+pub struct UniformLightSampler {
+    count:usize,
+}
+
+impl UniformLightSampler {
+    pub fn new (count:usize) -> Self {
+        UniformLightSampler {
+            count,
+        }
+    }
+}
+
+impl LightSampler for UniformLightSampler {
+    fn sample(&self, _p:&Vector3<f64>, u:f64) -> Option<(usize, f64)> {
+        if self.count == 0 {
+            return None;
+        }
+        let index = ((u * self.count as f64) as usize).min(self.count - 1);
+        Some((index, 1.0 / self.count as f64))
+    }
+
+    fn pmf(&self, _p:&Vector3<f64>, _index:usize) -> f64 {
+        if self.count == 0 {0.0} else {1.0 / self.count as f64}
+    }
+}
+
+pub struct PowerLightSampler {
+    distribution:Distribution1D,
+}
+
+impl PowerLightSampler {
+    pub fn new (powers:Vec<f64>) -> Self {
+        PowerLightSampler {
+            distribution:Distribution1D::new(powers),
+        }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _p:&Vector3<f64>, u:f64) -> Option<(usize, f64)> {
+        if self.distribution.count() == 0 {
+            return None;
+        }
+        let (index, pmf) = self.distribution.sample_discrete(u);
+        if pmf > 0.0 {Some((index, pmf))} else {None}
+    }
+
+    fn pmf(&self, _p:&Vector3<f64>, index:usize) -> f64 {
+        self.distribution.discrete_pdf(index)
+    }
+}
+
+struct LightNode {
+    bounds:LightBounds,
+    child_or_light:usize,
+    is_leaf:bool,
+}
+
+pub struct BvhLightSampler {
+    nodes:Vec<LightNode>,
+    infinite:Vec<usize>,
+    trails:Vec<Option<u64>>,
+}
+
+impl BvhLightSampler {
+    pub fn new (bounds:Vec<Option<LightBounds>>) -> Self {
+        let mut sampler = BvhLightSampler {
+            nodes:Vec::new(),
+            infinite:Vec::new(),
+            trails:vec![None; bounds.len()],
+        };
+        let mut bounded = Vec::new();
+        for (index, b) in bounds.into_iter().enumerate() {
+            match b {
+                Some(b) => bounded.push((index, b)),
+                None => sampler.infinite.push(index),
+            }
+        }
+        if !bounded.is_empty() {
+            sampler.build(&mut bounded, 0, 0);
+        }
+        sampler
+    }
+
+    fn evaluate_cost(b:&LightBounds, bounds:&Aabb, axis:usize) -> f64 {
+        let theta_o = b.cos_theta_o.clamp(-1.0, 1.0).acos();
+        let theta_e = b.cos_theta_e.clamp(-1.0, 1.0).acos();
+        let theta_w = (theta_o + theta_e).min(PI);
+        let sin_theta_o = safe_sqrt(1.0 - b.cos_theta_o * b.cos_theta_o);
+        let m_omega = 2.0 * PI * (1.0 - b.cos_theta_o)
+            + PI / 2.0 * (2.0 * theta_w * sin_theta_o - (theta_o - 2.0 * theta_w).cos() - 2.0 * theta_o * sin_theta_o + b.cos_theta_o);
+        let extent = Vector3::new(bounds.x.size(), bounds.y.size(), bounds.z.size());
+        let kr = if extent[axis] > 0.0 {extent.max() / extent[axis]} else {1.0};
+        b.phi * m_omega * kr * b.bounds.surface_area()
+    }
+
+    fn build(&mut self, lights:&mut [(usize, LightBounds)], bit_trail:u64, depth:u32) -> LightBounds {
+        if lights.len() == 1 {
+            let (index, bounds) = lights[0].clone();
+            self.nodes.push(LightNode { bounds:bounds.clone(), child_or_light:index, is_leaf:true });
+            self.trails[index] = Some(bit_trail);
+            return bounds;
+        }
+
+        let mut bounds = Aabb::empty();
+        let mut cmin = Vector3::repeat(f64::INFINITY);
+        let mut cmax = Vector3::repeat(f64::NEG_INFINITY);
+        for (_, b) in lights.iter() {
+            bounds = Aabb::merge(&bounds, &b.bounds);
+            let c = b.bounds.centroid();
+            cmin = cmin.inf(&c);
+            cmax = cmax.sup(&c);
+        }
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_axis = 0;
+        let mut best_bucket = 0;
+        let bucket_of = |c:f64, axis:usize| -> usize {
+            let t = (c - cmin[axis]) / (cmax[axis] - cmin[axis]);
+            ((t * BUCKETS as f64) as usize).min(BUCKETS - 1)
+        };
+        for axis in 0..3 {
+            if cmax[axis] <= cmin[axis] {
+                continue;
+            }
+            let mut buckets:Vec<Option<LightBounds>> = vec![None; BUCKETS];
+            for (_, b) in lights.iter() {
+                let i = bucket_of(b.bounds.centroid()[axis], axis);
+                buckets[i] = Some(match &buckets[i] {
+                    Some(acc) => LightBounds::merge(acc, b),
+                    None => b.clone(),
+                });
+            }
+            for split in 0..BUCKETS - 1 {
+                let below = buckets[..=split].iter().flatten().cloned().reduce(|a, b| LightBounds::merge(&a, &b));
+                let above = buckets[split + 1..].iter().flatten().cloned().reduce(|a, b| LightBounds::merge(&a, &b));
+                if let (Some(below), Some(above)) = (below, above) {
+                    let cost = Self::evaluate_cost(&below, &bounds, axis) + Self::evaluate_cost(&above, &bounds, axis);
+                    if cost > 0.0 && cost < best_cost {
+                        best_cost = cost;
+                        best_axis = axis;
+                        best_bucket = split;
+                    }
+                }
+            }
+        }
+
+        let mut mid = 0;
+        if best_cost < f64::INFINITY {
+            for i in 0..lights.len() {
+                if bucket_of(lights[i].1.bounds.centroid()[best_axis], best_axis) <= best_bucket {
+                    lights.swap(i, mid);
+                    mid += 1;
+                }
+            }
+        }
+        if mid == 0 || mid == lights.len() || depth >= 32 {
+            mid = lights.len() / 2;
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(LightNode { bounds:LightBounds::omnidirectional(bounds, 0.0), child_or_light:0, is_leaf:false });
+        let (left, right) = lights.split_at_mut(mid);
+        let left_bounds = self.build(left, bit_trail, depth + 1);
+        self.nodes[node].child_or_light = self.nodes.len();
+        let right_bounds = self.build(right, bit_trail | (1 << depth), depth + 1);
+        let merged = LightBounds::merge(&left_bounds, &right_bounds);
+        self.nodes[node].bounds = merged.clone();
+        merged
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let bounded = if self.nodes.is_empty() {0} else {1};
+        self.infinite.len() as f64 / (self.infinite.len() + bounded) as f64
+    }
+
+    fn children_importance(&self, node:usize, p:&Vector3<f64>) -> (f64, f64) {
+        let right = self.nodes[node].child_or_light;
+        (self.nodes[node + 1].bounds.importance(p), self.nodes[right].bounds.importance(p))
+    }
+
+    fn accumulate_pdf(&self, node:usize, pmf:f64, lights:&[Arc<dyn Hittable>], ray:&Ray, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        let n = &self.nodes[node];
+        if !n.bounds.bounds.hit(ray, &Interval::new(0.001, f64::INFINITY)) {
+            return 0.0;
+        }
+        if n.is_leaf {
+            if node == 0 && n.bounds.importance(origin) <= 0.0 {
+                return 0.0;
+            }
+            return pmf * lights[n.child_or_light].pdf_value(origin, direction);
+        }
+        let (c0, c1) = self.children_importance(node, origin);
+        if c0 + c1 <= 0.0 {
+            return 0.0;
+        }
+        let mut sum = 0.0;
+        if c0 > 0.0 {
+            sum += self.accumulate_pdf(node + 1, pmf * c0 / (c0 + c1), lights, ray, origin, direction);
+        }
+        if c1 > 0.0 {
+            sum += self.accumulate_pdf(n.child_or_light, pmf * c1 / (c0 + c1), lights, ray, origin, direction);
+        }
+        sum
+    }
+}
+
+impl LightSampler for BvhLightSampler {
+    fn sample(&self, p:&Vector3<f64>, u:f64) -> Option<(usize, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let count = self.infinite.len();
+            let index = ((u / p_infinite * count as f64) as usize).min(count - 1);
+            return Some((self.infinite[index], p_infinite / count as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(ONE_MINUS_EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = 0;
+        loop {
+            let n = &self.nodes[node];
+            if n.is_leaf {
+                if node > 0 || n.bounds.importance(p) > 0.0 {
+                    return Some((n.child_or_light, pmf));
+                }
+                return None;
+            }
+            let (c0, c1) = self.children_importance(node, p);
+            if c0 + c1 <= 0.0 {
+                return None;
+            }
+            let p0 = c0 / (c0 + c1);
+            if u < p0 {
+                node += 1;
+                u = (u / p0).min(ONE_MINUS_EPSILON);
+                pmf *= p0;
+            } else {
+                node = n.child_or_light;
+                u = ((u - p0) / (1.0 - p0)).min(ONE_MINUS_EPSILON);
+                pmf *= 1.0 - p0;
+            }
+        }
+    }
+
+    fn pmf(&self, p:&Vector3<f64>, index:usize) -> f64 {
+        let p_infinite = self.infinite_probability();
+        let mut trail = match self.trails[index] {
+            Some(trail) => trail,
+            None => return p_infinite / self.infinite.len() as f64,
+        };
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = 0;
+        loop {
+            let n = &self.nodes[node];
+            if n.is_leaf {
+                if node > 0 || n.bounds.importance(p) > 0.0 {
+                    return pmf;
+                }
+                return 0.0;
+            }
+            let (c0, c1) = self.children_importance(node, p);
+            if c0 + c1 <= 0.0 {
+                return 0.0;
+            }
+            if trail & 1 == 0 {
+                pmf *= c0 / (c0 + c1);
+                node += 1;
+            } else {
+                pmf *= c1 / (c0 + c1);
+                node = n.child_or_light;
+            }
+            trail >>= 1;
+        }
+    }
+
+    fn pdf_value(&self, lights:&[Arc<dyn Hittable>], origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        let p_infinite = self.infinite_probability();
+        let mut sum = 0.0;
+        for index in self.infinite.iter() {
+            sum += p_infinite / self.infinite.len() as f64 * lights[*index].pdf_value(origin, direction);
+        }
+        if !self.nodes.is_empty() {
+            let ray = Ray::new(*origin, *direction);
+            sum += self.accumulate_pdf(0, 1.0 - p_infinite, lights, &ray, origin, direction);
+        }
+        sum
+    }
+}
+
+pub enum LightSampling {
+    Uniform,
+    Power,
+    Bvh,
+}
+
+pub struct LightList {
+    lights:Vec<Arc<dyn Hittable>>,
+    sampler:Box<dyn LightSampler>,
+    bbox:Aabb,
+}
+
+impl LightList {
+    pub fn new (list:&HittableList, sampling:LightSampling) -> Self {
+        let lights = list.objects.clone();
+        let mut bounds:Vec<Option<LightBounds>> = lights.iter().map(|light| light.light_bounds()).collect();
+
+        let known:Vec<f64> = bounds.iter().flatten().map(|b| b.phi).filter(|phi| *phi > 0.0).collect();
+        let fallback = if known.is_empty() {1.0} else {known.iter().sum::<f64>() / known.len() as f64};
+        for b in bounds.iter_mut().flatten() {
+            if b.phi <= 0.0 {
+                *b = LightBounds::omnidirectional(b.bounds.clone(), fallback);
+            }
+        }
+
+        let sampler:Box<dyn LightSampler> = match sampling {
+            LightSampling::Uniform => Box::new(UniformLightSampler::new(lights.len())),
+            LightSampling::Power => {
+                Box::new(PowerLightSampler::new(bounds.iter().map(|b| b.as_ref().map_or(fallback, |b| b.phi)).collect()))
+            }
+            LightSampling::Bvh => Box::new(BvhLightSampler::new(bounds)),
+        };
+        LightList {
+            lights,
+            sampler,
+            bbox:list.bbox.clone(),
+        }
+    }
+}
+
+impl Hittable for LightList {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        let mut temp_rec = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+        for light in self.lights.iter() {
+            if light.hit(r, &Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec.clone();
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        self.sampler.pdf_value(&self.lights, origin, direction)
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        match self.sampler.sample(origin, random_double()) {
+            Some((index, _)) => self.lights[index].random(origin),
+            None => Vector3::new(1.0,0.0,0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(center:Vector3<f64>) -> Aabb {
+        Aabb::initial(&(center - Vector3::repeat(0.5)), &(center + Vector3::repeat(0.5)))
+    }
+
+    fn check_sampler(sampler:&dyn LightSampler, count:usize, p:&Vector3<f64>) -> Vec<f64> {
+        let pmfs:Vec<f64> = (0..count).map(|i| sampler.pmf(p, i)).collect();
+        assert!((pmfs.iter().sum::<f64>() - 1.0).abs() < 1e-12, "pmf sums to {}", pmfs.iter().sum::<f64>());
+
+        let n = 100_000;
+        let mut hits = vec![0usize; count];
+        for k in 0..n {
+            let (index, pmf) = sampler.sample(p, (k as f64 + 0.5) / n as f64).unwrap();
+            assert!((pmf - pmfs[index]).abs() < 1e-12, "sample pmf {} but pmf({}) = {}", pmf, index, pmfs[index]);
+            hits[index] += 1;
+        }
+        for i in 0..count {
+            let frequency = hits[i] as f64 / n as f64;
+            assert!((frequency - pmfs[i]).abs() < 1e-3, "light {} sampled {} expected {}", i, frequency, pmfs[i]);
+        }
+        pmfs
+    }
+
+    #[test]
+    fn uniform_sampler_is_consistent() {
+        let pmfs = check_sampler(&UniformLightSampler::new(5), 5, &Vector3::zeros());
+        assert!(pmfs.iter().all(|pmf| *pmf == 0.2));
+        assert!(UniformLightSampler::new(0).sample(&Vector3::zeros(), 0.5).is_none());
+    }
+
+    #[test]
+    fn power_sampler_is_consistent() {
+        let pmfs = check_sampler(&PowerLightSampler::new(vec![1.0, 3.0, 0.0, 4.0]), 4, &Vector3::zeros());
+        assert_eq!(pmfs, vec![0.125, 0.375, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn bvh_sampler_is_consistent() {
+        let p = Vector3::new(0.0, 0.0, 0.0);
+        let bounds = vec![
+            Some(LightBounds::omnidirectional(cube(Vector3::new(3.0, 0.0, 0.0)), 1.0)),
+            None,
+            Some(LightBounds::omnidirectional(cube(Vector3::new(-2.0, 1.0, 0.0)), 4.0)),
+            Some(LightBounds::omnidirectional(cube(Vector3::new(0.0, 5.0, 2.0)), 2.0)),
+            Some(LightBounds::new(cube(Vector3::new(0.0, 0.0, 4.0)), 8.0, Vector3::new(0.0, 0.0, 1.0), 1.0, 0.0, false)),
+            Some(LightBounds::omnidirectional(cube(Vector3::new(1.0, -6.0, -1.0)), 3.0)),
+        ];
+        let sampler = BvhLightSampler::new(bounds);
+        let pmfs = check_sampler(&sampler, 6, &p);
+        assert_eq!(pmfs[1], 0.5);
+        assert_eq!(pmfs[4], 0.0);
+    }
+}
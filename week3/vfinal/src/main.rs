@@ -15,76 +15,163 @@ mod rtw_stb_image;
 mod perlin;
 mod quad;
 mod constant_medium;
+mod density;
+mod heterogeneous_medium;
+mod cutout;
+mod distribution;
+mod environment;
+mod sky;
+mod light;
+mod emission;
+mod ies;
+mod light_sampler;
 mod onb;
 mod pdf;
+mod microfacet;
+mod spectrum;
+mod principled;
+mod bump;
+mod framebuffer;
+mod image_writer;
+mod tile;
+mod triangle;
+mod obj;
+mod scene;
+mod scenes;
 
-use constant_medium::ConstantMedium;
-use nalgebra::Vector3;
-use std::sync::Arc;
+use clap::{Args, Parser, Subcommand};
 
-use crate::color::write_color;
-use crate::ray::Ray;
-use crate::hittable::{HitRecord, Hittable, Translate, RotateY};
-use crate::hittable_list::HittableList;
-use crate::sphere::Sphere;
-use crate::interval::Interval;
-use crate::camera::Camera;
-use crate::material::{Material, Lambertian, Metal, Dielectric, DiffuseLight};
-use crate::rtweekend::{random_double, random_f64};
-use crate::vec3::{vec3_random, vec3_rand};
-use crate::bvh::BvhNode;
-use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
-use crate::quad::{Quad, abox};
+use crate::image_writer::check_output;
+use crate::rtweekend::seed_rng;
+use crate::scene::{load_scene, set_camera_field, Scene};
+use crate::scenes::{builtin_scene, SCENE_NAMES};
 
-fn main() {
-    let mut world = HittableList::new();
-
-    let red = Arc::new(Lambertian::new(Vector3::new(0.65, 0.05, 0.05)));
-    let white:Arc<dyn Material>= Arc::new(Lambertian::new(Vector3::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::new(Vector3::new(0.12,0.45,0.15)));
-    let light = Arc::new(DiffuseLight::initial(Vector3::new(15.0,15.0,15.0)));
+#[derive(Parser)]
+#[command(name = "light", about = "Path tracer from Ray Tracing: The Rest of Your Life")]
+struct Cli {
+    #[command(subcommand)]
+    command:Option<Command>,
+}
 
-    world.add(Arc::new(Quad::new(Vector3::new(555.0,0.0,0.0), Vector3::new(0.0,555.0,0.0), Vector3::new(0.0,0.0,555.0), green)));
-    world.add(Arc::new(Quad::new(Vector3::new(0.0,0.0,0.0), Vector3::new(0.0,555.0,0.0), Vector3::new(0.0,0.0,555.0), red)));
-    world.add(Arc::new(Quad::new(Vector3::new(343.0,554.0,332.0), Vector3::new(-130.0,0.0,0.0), Vector3::new(0.0,0.0,-105.0), light)));
-    world.add(Arc::new(Quad::new(Vector3::new(0.0,0.0,0.0), Vector3::new(555.0,0.0,0.0), Vector3::new(0.0,0.0,555.0), white.clone())));
-    world.add(Arc::new(Quad::new(Vector3::new(555.0,555.0,555.0), Vector3::new(-555.0,0.0,0.0), Vector3::new(0.0,0.0,-555.0), white.clone())));
-    world.add(Arc::new(Quad::new(Vector3::new(0.0,0.0,555.0), Vector3::new(555.0,0.0,0.0), Vector3::new(0.0,555.0,0.0), white.clone())));
-    
-    let box1 = abox(&Vector3::new(0.0,0.0,0.0),&Vector3::new(165.0,330.0,165.0),&white);
-    let box1 = Arc::new(RotateY::new(box1, 15.0));
-    let box1 = Arc::new(Translate::new(box1, Vector3::new(265.0,0.0,295.0)));
-    world.add(box1);
+#[derive(Subcommand)]
+enum Command {
+    /// Render a built-in scene or a TOML scene file
+    Render(RenderArgs),
+    /// List the built-in scenes
+    ListScenes,
+}
 
-    /*
-    let box2 = abox(&Vector3::new(0.0,0.0,0.0),&Vector3::new(165.0,165.0,165.0),&white);
-    let box2 = Arc::new(RotateY::new(box2, -18.0));
-    let box2 = Arc::new(Translate::new(box2, Vector3::new(130.0,0.0,65.0)));
-    world.add(box2);*/
-    let glass = Arc::new(Dielectric::new(1.5));
-    world.add(Arc::new(Sphere::new(Vector3::new(190.0,90.0,190.0), 90.0, glass)));
+#[derive(Args, Default)]
+struct RenderArgs {
+    /// Built-in scene name or path to a scene file
+    scene:String,
+    /// Output image (.png, .ppm, .exr or .hdr)
+    #[arg(short, long)]
+    output:Option<String>,
+    /// Samples per pixel
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    spp:Option<i64>,
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    width:Option<i64>,
+    /// Maximum path depth
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    max_depth:Option<i64>,
+    /// Worker threads (defaults to the number of cores)
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    threads:Option<i64>,
+    /// Random seed
+    #[arg(long)]
+    seed:Option<u64>,
+    /// Override any camera field, e.g. --set vfov=30 --set lookfrom=[0,1,5]
+    #[arg(long = "set", value_name = "FIELD=VALUE")]
+    set:Vec<String>,
+}
 
-    let world:Arc<dyn Hittable> = Arc::new(world);
+fn parse_override(assignment:&str) -> Result<(String, toml::Value), String> {
+    let (key, value) = assignment.split_once('=')
+        .ok_or_else(|| format!("override '{}' is not of the form FIELD=VALUE", assignment))?;
+    let key = key.trim().to_string();
+    let value = value.trim();
+    let parsed = toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    Ok((key, parsed))
+}
 
-    let mut lights = HittableList::new();
-    let m = Arc::new(DiffuseLight::initial(Vector3::new(15.0, 15.0, 15.0)));
-    lights.add(Arc::new(Quad::new(Vector3::new(343.0,554.0,332.0), Vector3::new(-130.0,0.0,0.0), Vector3::new(0.0,0.0,-105.0), m.clone())));
-    lights.add(Arc::new(Sphere::new(Vector3::new(190.0,90.0,190.0), 90.0, m)));
-    let lights:Arc<dyn Hittable> = Arc::new(lights);
+fn load(name:&str, seed:Option<u64>) -> Result<Scene, String> {
+    seed_rng(seed.unwrap_or(0), 0);
+    match builtin_scene(name) {
+        Some(scene) => Ok(scene),
+        None if std::path::Path::new(name).exists() => load_scene(name, seed),
+        None => Err(format!("'{}' is neither a built-in scene (see list-scenes) nor a scene file", name)),
+    }
+}
 
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 1.0;
-    cam.image_width  = 600;
-    cam.samples_per_pixel = 1000;
-    cam.max_depth = 50;
-    cam.background = Vector3::new(0.0, 0.0, 0.0);
+fn render(args:&RenderArgs) -> Result<(), String> {
+    let mut overrides = Vec::new();
+    for assignment in args.set.iter() {
+        overrides.push(parse_override(assignment)?);
+    }
+    let seed = match args.seed {
+        Some(seed) => Some(seed),
+        None => match overrides.iter().rev().find(|(key, _)| key == "seed") {
+            Some((_, toml::Value::Integer(seed))) if *seed >= 0 => Some(*seed as u64),
+            Some((key, toml::Value::Integer(seed))) => return Err(format!("camera field '{}' must be at least 0, got {}", key, seed)),
+            Some((key, _)) => return Err(format!("camera field '{}' expects an integer", key)),
+            None => None,
+        },
+    };
 
-    cam.vfov = 40.0;
-    cam.lookfrom = Vector3::new(278.0,278.0,-800.0);
-    cam.lookat = Vector3::new(278.0,278.0,0.0);
-    cam.vup = Vector3::new(0.0,1.0,0.0);
+    let mut scene = load(&args.scene, seed)?;
+    let cam = &mut scene.camera;
+    if let Some(output) = &args.output {
+        cam.output_file = output.clone();
+    }
+    if let Some(spp) = args.spp {
+        cam.samples_per_pixel = spp;
+    }
+    if let Some(width) = args.width {
+        cam.image_width = width;
+    }
+    if let Some(max_depth) = args.max_depth {
+        cam.max_depth = max_depth;
+    }
+    if let Some(threads) = args.threads {
+        cam.threads = threads;
+    }
+    if let Some(seed) = seed {
+        cam.seed = seed;
+    }
+    for (key, value) in overrides.iter() {
+        set_camera_field(cam, key, value)?;
+    }
 
-    cam.defocus_angle = 0.0;
+    let output = scene.camera.output_file.clone();
+    check_output(&output, scene.camera.bit_depth).map_err(|e| format!("cannot write image file '{}': {}", output, e))?;
+    scene.camera.render(&scene.world, &scene.lights)
+        .map_err(|e| format!("could not write image file '{}': {}", output, e))?;
+    Ok(())
+}
 
-    cam.render(&world, &lights);
+fn main() {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Some(Command::Render(args)) => render(&args),
+        Some(Command::ListScenes) => {
+            for (name, description) in SCENE_NAMES.iter() {
+                println!("{:<20} {}", name, description);
+            }
+            Ok(())
+        }
+        None => render(&RenderArgs {
+            scene:String::from("cornell-glass"),
+            ..Default::default()
+        }),
+    };
+    if let Err(e) = result {
+        eprintln!("ERROR: {}", e);
+        std::process::exit(1);
+    }
 }
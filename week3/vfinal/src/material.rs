@@ -1,8 +1,11 @@
 use nalgebra::Vector3;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+use crate::emission::EmissionProfile;
 use crate::onb::Onb;
-use crate::pdf::{CosinePdf, Pdf, SpherePdf};
+use crate::microfacet::{fresnel_complex, fresnel_dielectric, TrowbridgeReitz};
+use crate::pdf::{CosinePdf, MicrofacetReflectionPdf, Pdf, RoughDielectricPdf, SpherePdf};
 use crate::ray::Ray;
 use crate::hittable::HitRecord;
 use crate::vec3::{random_cosine_direction, random_on_hemisphere, random_unit_vector, reflect, refract};
@@ -40,6 +43,35 @@ pub trait Material: Send + Sync {
     fn scattering_pdf(&self, r_in:&Ray, rec:&HitRecord, scattered:&Ray) -> f64 {
         0.0
     }
+
+    fn scattering_value(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, scattered:&Ray) -> Vector3<f64> {
+        srec.attenuation * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn absorption(&self, rec:&HitRecord) -> Vector3<f64> {
+        Vector3::zeros()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    fn is_two_sided(&self) -> bool {
+        false
+    }
+
+    fn radiant_exitance(&self, rec:&HitRecord) -> Vector3<f64> {
+        let r_in = Ray::initial(rec.p + rec.normal, -rec.normal, 0.0);
+        self.emitted(&r_in, rec, rec.u, rec.v, &rec.p) * PI
+    }
+}
+
+pub fn absorption_from_transmittance(transmittance:Vector3<f64>, distance:f64) -> Vector3<f64> {
+    transmittance.map(|t| -t.clamp(1e-6, 1.0).ln() / distance)
 }
 
 pub struct Lambertian {
@@ -105,16 +137,173 @@ impl Material for Metal {
     }
 }
 
+pub struct Conductor {
+    pub eta:Vector3<f64>,
+    pub k:Vector3<f64>,
+    pub roughness_u:f64,
+    pub roughness_v:f64,
+}
+
+impl Conductor {
+    pub fn new (eta:Vector3<f64>, k:Vector3<f64>, roughness:f64) -> Self {
+        Self::anisotropic(eta, k, roughness, roughness)
+    }
+
+    pub fn anisotropic (eta:Vector3<f64>, k:Vector3<f64>, roughness_u:f64, roughness_v:f64) -> Self {
+        Conductor {
+            eta,
+            k,
+            roughness_u:roughness_u.clamp(0.0, 1.0),
+            roughness_v:roughness_v.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn from_reflectance (reflectance:Vector3<f64>, roughness:f64) -> Self {
+        let r = reflectance.map(|c| c.clamp(0.0, 0.9999));
+        let k = r.map(|c| 2.0 * c.sqrt() / (1.0 - c).sqrt());
+        Self::new(Vector3::new(1.0,1.0,1.0), k, roughness)
+    }
+
+    pub fn preset (name:&str, roughness:f64) -> Option<Self> {
+        let (eta, k) = match name {
+            "gold" => (Vector3::new(0.143, 0.374, 1.442), Vector3::new(3.983, 2.385, 1.603)),
+            "silver" => (Vector3::new(0.155, 0.117, 0.138), Vector3::new(4.828, 3.122, 2.147)),
+            "copper" => (Vector3::new(0.200, 0.924, 1.102), Vector3::new(3.912, 2.452, 2.142)),
+            "aluminium" | "aluminum" => (Vector3::new(1.657, 0.880, 0.521), Vector3::new(9.224, 6.270, 4.837)),
+            _ => return None,
+        };
+        Some(Self::new(eta, k, roughness))
+    }
+
+    fn distribution(&self) -> TrowbridgeReitz {
+        TrowbridgeReitz::new(
+            TrowbridgeReitz::roughness_to_alpha(self.roughness_u),
+            TrowbridgeReitz::roughness_to_alpha(self.roughness_v),
+        )
+    }
+
+    fn frame(rec:&HitRecord) -> Onb {
+        let mut uvw = Onb::new();
+        uvw.build_from_w_tangent(&rec.normal, &rec.dpdu);
+        uvw
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in:&Ray, rec:&HitRecord, srec:&mut ScatterRecord) -> bool {
+        let uvw = Self::frame(rec);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let distribution = self.distribution();
+        if distribution.is_smooth() {
+            srec.attenuation = fresnel_complex(wo.z, &self.eta, &self.k);
+            srec.skip_pdf = true;
+            srec.skip_pdf_ray = Ray::initial(rec.p, reflect(&r_in.direction(), &rec.normal), r_in.time());
+            return true;
+        }
+        srec.attenuation = Vector3::new(1.0,1.0,1.0);
+        srec.pdf_ptr = Arc::new(MicrofacetReflectionPdf::new(uvw, wo, distribution));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in:&Ray, rec:&HitRecord, scattered:&Ray) -> f64 {
+        let uvw = Self::frame(rec);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+        let wi = uvw.to_local(&scattered.direction().normalize());
+        self.distribution().reflection_pdf(&wo, &wi)
+    }
+
+    fn scattering_value(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, scattered:&Ray) -> Vector3<f64> {
+        let uvw = Self::frame(rec);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+        let wi = uvw.to_local(&scattered.direction().normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vector3::zeros();
+        }
+        let wm = wo + wi;
+        if wm.norm_squared() == 0.0 {
+            return Vector3::zeros();
+        }
+        let wm = wm.normalize();
+        let distribution = self.distribution();
+        let fresnel = fresnel_complex(wo.dot(&wm).abs(), &self.eta, &self.k);
+        fresnel * (distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    Cauchy { a:f64, b:f64 },
+    Sellmeier { b:[f64;3], c:[f64;3] },
+}
+
+impl Dispersion {
+    pub fn ior(&self, wavelength:f64) -> f64 {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+
+    pub fn preset(name:&str) -> Option<Self> {
+        match name {
+            "bk7" => Some(Dispersion::Sellmeier {
+                b:[1.03961212, 0.231792344, 1.01046945],
+                c:[0.00600069867, 0.0200179144, 103.560653],
+            }),
+            "fused_silica" => Some(Dispersion::Sellmeier {
+                b:[0.6961663, 0.4079426, 0.8974794],
+                c:[0.00467914826, 0.0135120631, 97.9340025],
+            }),
+            "diamond" => Some(Dispersion::Sellmeier {
+                b:[4.3356, 0.3306, 0.0],
+                c:[0.011236, 0.030625, 0.0],
+            }),
+            _ => None,
+        }
+    }
+}
+
 pub struct Dielectric {
     pub refraction_index:f64,
+    pub absorption:Vector3<f64>,
+    pub dispersion:Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(refraction_ind:f64) -> Self{
+        Self::with_absorption(refraction_ind, Vector3::zeros())
+    }
+    pub fn with_absorption(refraction_index:f64, absorption:Vector3<f64>) -> Self {
         Dielectric {
-            refraction_index:refraction_ind,
+            refraction_index,
+            absorption,
+            dispersion:None,
         }
     }
+    pub fn with_dispersion(dispersion:Dispersion, absorption:Vector3<f64>) -> Self {
+        Dielectric {
+            refraction_index:dispersion.ior(587.6),
+            absorption,
+            dispersion:Some(dispersion),
+        }
+    }
+    pub fn ior(&self, wavelength:f64) -> f64 {
+        match self.dispersion {
+            Some(dispersion) if wavelength > 0.0 => dispersion.ior(wavelength),
+            _ => self.refraction_index,
+        }
+    }
+    pub fn with_transmittance(refraction_index:f64, transmittance:Vector3<f64>, distance:f64) -> Self {
+        Self::with_absorption(refraction_index, absorption_from_transmittance(transmittance, distance))
+    }
     pub fn reflectance(cosine:f64, refraction_index:f64) -> f64 {
         let mut r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
         r0 = r0 * r0;
@@ -128,9 +317,10 @@ impl Material for Dielectric {
         srec.pdf_ptr = Arc::new(SpherePdf::new());
         srec.skip_pdf = true;
 
-        let mut ri = self.refraction_index;
+        let refraction_index = self.ior(r_in.wavelength);
+        let mut ri = refraction_index;
         if rec.front_face {
-            ri = 1.0 / self.refraction_index;
+            ri = 1.0 / refraction_index;
         }
         let unit_direction = r_in.direction().normalize();
         let cos_theta = -unit_direction.dot(&rec.normal).min(1.0);
@@ -146,32 +336,162 @@ impl Material for Dielectric {
         srec.skip_pdf_ray = Ray::initial(rec.p, direction, r_in.time());
         true
     }
+
+    fn absorption(&self, rec:&HitRecord) -> Vector3<f64> {
+        self.absorption
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+}
+
+pub struct RoughDielectric {
+    pub refraction_index:f64,
+    pub roughness:Arc<dyn Texture>,
+    pub absorption:Vector3<f64>,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index:f64, roughness:f64) -> Self {
+        Self::with_texture(refraction_index, Arc::new(SolidColor::new(Vector3::new(roughness, roughness, roughness))))
+    }
+
+    pub fn with_texture(refraction_index:f64, roughness:Arc<dyn Texture>) -> Self {
+        RoughDielectric {
+            refraction_index,
+            roughness,
+            absorption:Vector3::zeros(),
+        }
+    }
+
+    fn distribution(&self, rec:&HitRecord) -> TrowbridgeReitz {
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p).x.clamp(0.0, 1.0);
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    fn eta(&self, rec:&HitRecord) -> f64 {
+        if rec.front_face {self.refraction_index} else {1.0 / self.refraction_index}
+    }
+
+    fn frame(rec:&HitRecord) -> Onb {
+        let mut uvw = Onb::new();
+        uvw.build_from_w(&rec.normal);
+        uvw
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in:&Ray, rec:&HitRecord, srec:&mut ScatterRecord) -> bool {
+        let uvw = Self::frame(rec);
+        let unit_direction = r_in.direction().normalize();
+        let wo = uvw.to_local(&-unit_direction);
+        let eta = self.eta(rec);
+        let distribution = self.distribution(rec);
+        srec.attenuation = Vector3::new(1.0,1.0,1.0);
+
+        if distribution.is_smooth() {
+            let cos_theta = wo.z.min(1.0);
+            let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
+            let direction = if sin_theta / eta > 1.0 || fresnel_dielectric(cos_theta, eta) > random_double() {
+                reflect(&unit_direction, &rec.normal)
+            } else {
+                refract(&unit_direction, &rec.normal, 1.0 / eta)
+            };
+            srec.skip_pdf = true;
+            srec.skip_pdf_ray = Ray::initial(rec.p, direction, r_in.time());
+            return true;
+        }
+
+        srec.pdf_ptr = Arc::new(RoughDielectricPdf::new(uvw, wo, eta, distribution));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in:&Ray, rec:&HitRecord, scattered:&Ray) -> f64 {
+        let uvw = Self::frame(rec);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+        let wi = uvw.to_local(&scattered.direction().normalize());
+        self.distribution(rec).dielectric_pdf(&wo, &wi, self.eta(rec))
+    }
+
+    fn scattering_value(&self, r_in:&Ray, rec:&HitRecord, srec:&ScatterRecord, scattered:&Ray) -> Vector3<f64> {
+        let uvw = Self::frame(rec);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+        let wi = uvw.to_local(&scattered.direction().normalize());
+        let f = self.distribution(rec).dielectric_f(&wo, &wi, self.eta(rec));
+        srec.attenuation * (f * wi.z.abs())
+    }
+
+    fn absorption(&self, rec:&HitRecord) -> Vector3<f64> {
+        self.absorption
+    }
 }
 
 pub struct DiffuseLight {
     tex:Arc<dyn Texture>,
+    pub two_sided:bool,
+    pub profile:Option<Arc<dyn EmissionProfile>>,
+    pub scale:f64,
 }
 
 impl DiffuseLight {
     pub fn new (tex:Arc<dyn Texture>) -> Self {
         DiffuseLight {
             tex:tex.clone(),
+            two_sided:false,
+            profile:None,
+            scale:1.0,
         }
     }
 
     pub fn initial (emit:Vector3<f64>) -> Self {
-        DiffuseLight {
-            tex:Arc::new(SolidColor::new(emit.clone())),
+        Self::new(Arc::new(SolidColor::new(emit.clone())))
+    }
+
+    pub fn set_power(&mut self, power:f64, area:f64) {
+        self.scale = power / (area * self.sides() * self.profile_integral());
+    }
+
+    fn sides(&self) -> f64 {
+        if self.two_sided {2.0} else {1.0}
+    }
+
+    fn profile_integral(&self) -> f64 {
+        match &self.profile {
+            Some(profile) => profile.integral(),
+            None => PI,
         }
     }
 }
 
 impl Material for DiffuseLight {
     fn emitted(&self, r_in:&Ray, rec:&HitRecord, u:f64, v:f64, p:&Vector3<f64>) -> Vector3<f64> {
-        if !rec.front_face {
+        if !rec.front_face && !self.two_sided {
             return Vector3::new(0.0,0.0,0.0);
         }
-        self.tex.value(u,v,&p)
+        let radiance = self.tex.value(u,v,&p) * self.scale;
+        match &self.profile {
+            Some(profile) => {
+                let mut uvw = Onb::new();
+                uvw.build_from_w_tangent(&rec.normal, &rec.dpdu);
+                radiance * profile.value(&uvw.to_local(&(-r_in.direction()).normalize()))
+            }
+            None => radiance,
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn is_two_sided(&self) -> bool {
+        self.two_sided
+    }
+
+    fn radiant_exitance(&self, rec:&HitRecord) -> Vector3<f64> {
+        self.tex.value(rec.u, rec.v, &rec.p) * (self.scale * self.sides() * self.profile_integral())
     }
 }
 
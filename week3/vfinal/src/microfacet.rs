@@ -0,0 +1,245 @@
+use nalgebra::Vector3;
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x:f64,
+    alpha_y:f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x:f64, alpha_y:f64) -> Self {
+        TrowbridgeReitz {
+            alpha_x:alpha_x.max(1e-4),
+            alpha_y:alpha_y.max(1e-4),
+        }
+    }
+
+    pub fn roughness_to_alpha(roughness:f64) -> f64 {
+        roughness * roughness
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    fn tan2_theta(w:&Vector3<f64>) -> f64 {
+        let cos2 = w.z * w.z;
+        (1.0 - cos2).max(0.0) / cos2
+    }
+
+    pub fn d(&self, wm:&Vector3<f64>) -> f64 {
+        let tan2 = Self::tan2_theta(wm);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let cos4 = wm.z * wm.z * wm.z * wm.z;
+        let sin2 = (1.0 - wm.z * wm.z).max(0.0);
+        let (cos2_phi, sin2_phi) = if sin2 == 0.0 {
+            (1.0, 0.0)
+        } else {
+            (wm.x * wm.x / sin2, wm.y * wm.y / sin2)
+        };
+        let e = tan2 * (cos2_phi / (self.alpha_x * self.alpha_x) + sin2_phi / (self.alpha_y * self.alpha_y));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e) * (1.0 + e))
+    }
+
+    pub fn lambda(&self, w:&Vector3<f64>) -> f64 {
+        let tan2 = Self::tan2_theta(w);
+        if tan2.is_infinite() || tan2.is_nan() {
+            return 0.0;
+        }
+        let sin2 = (1.0 - w.z * w.z).max(0.0);
+        let alpha2 = if sin2 == 0.0 {
+            self.alpha_x * self.alpha_x
+        } else {
+            (w.x * w.x * self.alpha_x * self.alpha_x + w.y * w.y * self.alpha_y * self.alpha_y) / sin2
+        };
+        ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w:&Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo:&Vector3<f64>, wi:&Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    pub fn d_visible(&self, w:&Vector3<f64>, wm:&Vector3<f64>) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    pub fn reflection_pdf(&self, wo:&Vector3<f64>, wi:&Vector3<f64>) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = wo + wi;
+        if wm.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let wm = wm.normalize();
+        self.d_visible(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    fn dielectric_half_vector(wo:&Vector3<f64>, wi:&Vector3<f64>, eta:f64) -> Option<(Vector3<f64>, f64)> {
+        let reflect = wo.z * wi.z > 0.0;
+        let etap = if reflect {1.0} else if wo.z > 0.0 {eta} else {1.0 / eta};
+        let wm = wi * etap + wo;
+        if wo.z == 0.0 || wi.z == 0.0 || wm.norm_squared() == 0.0 {
+            return None;
+        }
+        let mut wm = wm.normalize();
+        if wm.z < 0.0 {
+            wm = -wm;
+        }
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    pub fn dielectric_f(&self, wo:&Vector3<f64>, wi:&Vector3<f64>, eta:f64) -> f64 {
+        let (wm, etap) = match Self::dielectric_half_vector(wo, wi, eta) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo.dot(&wm), eta);
+        if etap == 1.0 {
+            return self.d(&wm) * self.g(wo, wi) * fresnel / (4.0 * wi.z * wo.z).abs();
+        }
+        let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2) * wi.z * wo.z;
+        let ft = self.d(&wm) * (1.0 - fresnel) * self.g(wo, wi) * (wi.dot(&wm) * wo.dot(&wm) / denom).abs();
+        ft / (etap * etap)
+    }
+
+    pub fn dielectric_pdf(&self, wo:&Vector3<f64>, wi:&Vector3<f64>, eta:f64) -> f64 {
+        let (wm, etap) = match Self::dielectric_half_vector(wo, wi, eta) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        if etap == 1.0 {
+            return self.d_visible(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * reflectance;
+        }
+        let denom = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+        let dwm_dwi = wi.dot(&wm).abs() / denom;
+        self.d_visible(wo, &wm) * dwm_dwi * (1.0 - reflectance)
+    }
+
+    pub fn sample_dielectric(&self, wo:&Vector3<f64>, eta:f64, u:f64, u1:f64, u2:f64) -> Option<Vector3<f64>> {
+        let wm = self.sample_wm(wo, u1, u2);
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        if u < reflectance {
+            let wi = -wo + 2.0 * wo.dot(&wm) * wm;
+            return if wo.z * wi.z > 0.0 {Some(wi)} else {None};
+        }
+        let (eta, n) = if wo.dot(&wm) < 0.0 {(1.0 / eta, -wm)} else {(eta, wm)};
+        let cos_theta_i = wo.dot(&n);
+        let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+        if sin2_theta_t >= 1.0 {
+            return None;
+        }
+        let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+        let wi = -wo / eta + (cos_theta_i / eta - cos_theta_t) * n;
+        if wo.z * wi.z < 0.0 {Some(wi)} else {None}
+    }
+
+    pub fn sample_wm(&self, w:&Vector3<f64>, u1:f64, u2:f64) -> Vector3<f64> {
+        let mut wh = Vector3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vector3::new(0.0, 0.0, 1.0).cross(&wh).normalize()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let px = r * phi.cos();
+        let mut py = r * phi.sin();
+        let h = (1.0 - px * px).max(0.0).sqrt();
+        let s = 0.5 * (1.0 + wh.z);
+        py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+pub fn gtr1_d(cos_theta_h:f64, alpha:f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+pub fn sample_gtr1(alpha:f64, u1:f64, u2:f64) -> Vector3<f64> {
+    let a2 = alpha * alpha;
+    let cos_theta = if alpha >= 1.0 {
+        (1.0 - u1).sqrt()
+    } else {
+        ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt()
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn schlick_weight(cos_theta:f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+pub fn fresnel_dielectric(cos_theta_i:f64, eta:f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+fn fresnel_conductor(cos_theta_i:f64, eta:f64, k:f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i.clamp(0.0, 1.0) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_complex(cos_theta_i:f64, eta:&Vector3<f64>, k:&Vector3<f64>) -> Vector3<f64> {
+    Vector3::new(
+        fresnel_conductor(cos_theta_i, eta.x, k.x),
+        fresnel_conductor(cos_theta_i, eta.y, k.y),
+        fresnel_conductor(cos_theta_i, eta.z, k.z),
+    )
+}
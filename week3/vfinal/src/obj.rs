@@ -0,0 +1,345 @@
+use nalgebra::{Vector2, Vector3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::principled::Principled;
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::triangle::{MeshData, TriangleMesh};
+
+struct MtlMaterial {
+    kd:Vector3<f64>,
+    ks:Vector3<f64>,
+    ke:Vector3<f64>,
+    ns:f64,
+    ni:f64,
+    dissolve:f64,
+    illum:i64,
+    map_kd:Option<String>,
+    roughness:Option<f64>,
+    metallic:Option<f64>,
+    sheen:Option<f64>,
+    clearcoat:Option<f64>,
+    clearcoat_roughness:Option<f64>,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        MtlMaterial {
+            kd:Vector3::new(0.8, 0.8, 0.8),
+            ks:Vector3::zeros(),
+            ke:Vector3::zeros(),
+            ns:0.0,
+            ni:1.0,
+            dissolve:1.0,
+            illum:2,
+            map_kd:None,
+            roughness:None,
+            metallic:None,
+            sheen:None,
+            clearcoat:None,
+            clearcoat_roughness:None,
+        }
+    }
+
+    fn is_pbr(&self) -> bool {
+        self.roughness.is_some() || self.metallic.is_some() || self.sheen.is_some() || self.clearcoat.is_some()
+    }
+
+    fn base_color(&self, dir:&Path) -> Result<Arc<dyn Texture>, String> {
+        if let Some(map) = &self.map_kd {
+            return match ImageTexture::from_path(&dir.join(map)) {
+                Some(tex) => Ok(Arc::new(tex)),
+                None => Err(format!("could not load image '{}'", dir.join(map).display())),
+            };
+        }
+        Ok(Arc::new(SolidColor::new(self.kd)))
+    }
+
+    fn to_principled(&self, dir:&Path) -> Result<Principled, String> {
+        let constant = |v:f64| -> Arc<dyn Texture> { Arc::new(SolidColor::new(Vector3::new(v, v, v))) };
+        let mut mat = Principled::initial(self.base_color(dir)?);
+        if let Some(r) = self.roughness {
+            mat.roughness = constant(r);
+        }
+        if let Some(m) = self.metallic {
+            mat.metallic = constant(m);
+        }
+        if let Some(s) = self.sheen {
+            mat.sheen = constant(s);
+        }
+        if let Some(c) = self.clearcoat {
+            mat.clearcoat = constant(c);
+        }
+        if let Some(r) = self.clearcoat_roughness {
+            mat.clearcoat_gloss = constant(1.0 - r);
+        }
+        if self.dissolve < 1.0 {
+            mat.transmission = constant(1.0 - self.dissolve);
+        }
+        if self.ni > 1.0 {
+            mat.ior = constant(self.ni);
+        }
+        Ok(mat)
+    }
+
+    fn to_material(&self, dir:&Path) -> Result<Arc<dyn Material>, String> {
+        if self.ke.max() > 0.0 {
+            return Ok(Arc::new(DiffuseLight::initial(self.ke)));
+        }
+        if self.is_pbr() {
+            return Ok(Arc::new(self.to_principled(dir)?));
+        }
+        if matches!(self.illum, 4 | 6 | 7) || self.dissolve < 1.0 {
+            return Ok(Arc::new(Dielectric::new(self.ni)));
+        }
+        if self.illum == 3 || (self.ks.max() > 0.0 && self.ks.max() >= self.kd.max()) {
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            return Ok(Arc::new(Metal::new(self.ks, fuzz)));
+        }
+        Ok(Arc::new(Lambertian::initial(self.base_color(dir)?)))
+    }
+}
+
+fn parse_floats<const N:usize>(args:&[&str], location:&str) -> Result<[f64;N], String> {
+    if args.len() < N {
+        return Err(format!("{}: expected {} numbers, found {}", location, N, args.len()));
+    }
+    let mut values = [0.0;N];
+    for (value, arg) in values.iter_mut().zip(args.iter()) {
+        *value = arg.parse().map_err(|_| format!("{}: '{}' is not a number", location, arg))?;
+    }
+    Ok(values)
+}
+
+fn resolve_index(token:&str, count:usize, location:&str) -> Result<usize, String> {
+    let index:i64 = token.parse().map_err(|_| format!("{}: bad vertex index '{}'", location, token))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{}: vertex index {} out of range (have {})", location, index, count));
+    }
+    Ok(resolved as usize)
+}
+
+fn load_mtl(path:&Path) -> Result<HashMap<String, MtlMaterial>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut materials = HashMap::new();
+    let mut current:Option<(String, MtlMaterial)> = None;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let location = format!("{}:{}", path.display(), line_number + 1);
+        let tokens:Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+        if tokens[0] == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            current = Some((tokens[1..].join(" "), MtlMaterial::new()));
+            continue;
+        }
+        let mtl = match current.as_mut() {
+            Some((_, mtl)) => mtl,
+            None => return Err(format!("{}: '{}' before any newmtl", location, tokens[0])),
+        };
+        let args = &tokens[1..];
+        match tokens[0] {
+            "Kd" => mtl.kd = Vector3::from(parse_floats::<3>(args, &location)?),
+            "Ks" => mtl.ks = Vector3::from(parse_floats::<3>(args, &location)?),
+            "Ke" => mtl.ke = Vector3::from(parse_floats::<3>(args, &location)?),
+            "Ns" => mtl.ns = parse_floats::<1>(args, &location)?[0],
+            "Ni" => mtl.ni = parse_floats::<1>(args, &location)?[0],
+            "d" => mtl.dissolve = parse_floats::<1>(args, &location)?[0],
+            "Tr" => mtl.dissolve = 1.0 - parse_floats::<1>(args, &location)?[0],
+            "Pr" => mtl.roughness = Some(parse_floats::<1>(args, &location)?[0]),
+            "Pm" => mtl.metallic = Some(parse_floats::<1>(args, &location)?[0]),
+            "Ps" => mtl.sheen = Some(parse_floats::<1>(args, &location)?[0]),
+            "Pc" => mtl.clearcoat = Some(parse_floats::<1>(args, &location)?[0]),
+            "Pcr" => mtl.clearcoat_roughness = Some(parse_floats::<1>(args, &location)?[0]),
+            "illum" => mtl.illum = parse_floats::<1>(args, &location)?[0] as i64,
+            "map_Kd" => match args.last() {
+                Some(file) => mtl.map_kd = Some(file.to_string()),
+                None => return Err(format!("{}: map_Kd without a file name", location)),
+            },
+            _ => {}
+        }
+    }
+    if let Some((name, mtl)) = current.take() {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+pub fn load_obj(filename:&str, default_mat:Arc<dyn Material>) -> Result<TriangleMesh, String> {
+    let source = fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    Ok(TriangleMesh::new(parse_obj(filename, &source, default_mat)?))
+}
+
+pub fn parse_obj(filename:&str, source:&str, default_mat:Arc<dyn Material>) -> Result<MeshData, String> {
+    let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+    let mut mesh = MeshData::new();
+    mesh.materials.push(default_mat);
+    let mut material_ids:HashMap<String, usize> = HashMap::new();
+    let mut library:HashMap<String, MtlMaterial> = HashMap::new();
+    let mut current_material = 0;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let location = format!("{}:{}", filename, line_number + 1);
+        let tokens:Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+        let args = &tokens[1..];
+        match tokens[0] {
+            "v" => mesh.positions.push(Vector3::from(parse_floats::<3>(args, &location)?)),
+            "vn" => mesh.normals.push(Vector3::from(parse_floats::<3>(args, &location)?)),
+            "vt" => {
+                let uv = parse_floats::<1>(args, &location)?;
+                let v = if args.len() > 1 { parse_floats::<2>(args, &location)?[1] } else { 0.0 };
+                mesh.uvs.push(Vector2::new(uv[0], v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(format!("{}: face needs at least 3 vertices", location));
+                }
+                let mut corners = Vec::new();
+                for corner in args {
+                    let mut parts = corner.split('/');
+                    let p = resolve_index(parts.next().unwrap_or(""), mesh.positions.len(), &location)?;
+                    let t = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, mesh.uvs.len(), &location)?),
+                        _ => None,
+                    };
+                    let n = match parts.next() {
+                        Some(n) if !n.is_empty() => Some(resolve_index(n, mesh.normals.len(), &location)?),
+                        _ => None,
+                    };
+                    corners.push((p, t, n));
+                }
+                for k in 1..corners.len() - 1 {
+                    let (a, b, c) = (corners[0], corners[k], corners[k + 1]);
+                    let uv = match (a.1, b.1, c.1) {
+                        (Some(ta), Some(tb), Some(tc)) => Some([ta, tb, tc]),
+                        _ => None,
+                    };
+                    let normal = match (a.2, b.2, c.2) {
+                        (Some(na), Some(nb), Some(nc)) => Some([na, nb, nc]),
+                        _ => None,
+                    };
+                    mesh.add_triangle([a.0, b.0, c.0], normal, uv, current_material);
+                }
+            }
+            "mtllib" => {
+                for lib in args {
+                    let mtl_path = dir.join(lib);
+                    library.extend(load_mtl(&mtl_path).map_err(|e| format!("{}: {}", location, e))?);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_material = match material_ids.get(&name) {
+                    Some(&id) => id,
+                    None => match library.get(&name) {
+                        Some(mtl) => {
+                            mesh.materials.push(mtl.to_material(dir).map_err(|e| format!("{}: {}", location, e))?);
+                            material_ids.insert(name, mesh.materials.len() - 1);
+                            mesh.materials.len() - 1
+                        }
+                        None => return Err(format!("{}: unknown material '{}'", location, name)),
+                    },
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source:&str) -> Result<MeshData, String> {
+        parse_obj("test.obj", source, Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))))
+    }
+
+    const SQUARE:&str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let mesh = parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.position_indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.normal_indices, vec![None, None]);
+        assert_eq!(mesh.uv_indices, vec![None, None]);
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let mesh = parse(&format!("{}f -4 -3 -2\nv 2 2 0\nf -1 -2 -3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.position_indices, vec![[0, 1, 2], [4, 3, 2]]);
+    }
+
+    #[test]
+    fn reads_texture_coordinates_and_normals() {
+        let source = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1//1 3//1 4//1\n", SQUARE);
+        let mesh = parse(&source).unwrap();
+        assert_eq!(mesh.uv_indices, vec![Some([0, 1, 2]), None]);
+        assert_eq!(mesh.normal_indices, vec![Some([0, 0, 0]), Some([0, 0, 0])]);
+        assert_eq!(mesh.uvs[2], Vector2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn missing_normals_on_any_corner_drop_vertex_normals() {
+        let mesh = parse(&format!("{}vn 0 0 1\nf 1//1 2//1 3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.normal_indices, vec![None]);
+    }
+
+    #[test]
+    fn ignores_comments_and_unknown_statements() {
+        let mesh = parse(&format!("# comment\no square\ns off\n{}g group\nf 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangle_count(), 1);
+    }
+
+    #[test]
+    fn faces_before_usemtl_use_default() {
+        let mesh = parse(&format!("{}f 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.materials.len(), 1);
+        assert_eq!(mesh.material_indices, vec![0]);
+    }
+
+    #[test]
+    fn material_errors_fail_the_load() {
+        let unknown = parse(&format!("{}usemtl missing\nf 1 2 3\n", SQUARE)).err().unwrap();
+        assert!(unknown.starts_with("test.obj:5:") && unknown.contains("unknown material 'missing'"), "{}", unknown);
+
+        let library = parse("mtllib missing.mtl\n").err().unwrap();
+        assert!(library.starts_with("test.obj:1:") && library.contains("missing.mtl"), "{}", library);
+
+        let dir = std::env::temp_dir().join(format!("light-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("textured.mtl"), "newmtl textured\nKd 1 1 1\nmap_Kd missing.png\n").unwrap();
+        let obj = dir.join("textured.obj");
+        let source = format!("mtllib textured.mtl\n{}usemtl textured\nf 1 2 3\n", SQUARE);
+        let image = parse_obj(&obj.to_string_lossy(), &source, Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))).err().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(image.contains("textured.obj:6:") && image.contains("could not load image"), "{}", image);
+    }
+
+    #[test]
+    fn reports_bad_faces_with_location() {
+        let out_of_range = parse(&format!("{}f 1 2 5\n", SQUARE)).err().unwrap();
+        assert!(out_of_range.starts_with("test.obj:5:"), "{}", out_of_range);
+        assert!(out_of_range.contains("out of range"), "{}", out_of_range);
+        assert!(parse(&format!("{}f -5 1 2\n", SQUARE)).is_err());
+        assert!(parse(&format!("{}f 0 1 2\n", SQUARE)).is_err());
+        assert!(parse(&format!("{}f 1 2\n", SQUARE)).is_err());
+        assert!(parse(&format!("{}f 1/4 2 3\n", SQUARE)).is_err());
+        assert!(parse("v 0 0 x\n").is_err());
+        assert!(parse("v 0 0\n").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use nalgebra::Vector3;
 
+#[derive(Clone)]
 pub struct Onb {
     axis:[Vector3<f64>;3],
 }
@@ -31,6 +32,10 @@ impl Onb {
         a.x*self.axis[0] + a.y*self.axis[1] + a.z*self.axis[2]
     }
 
+    pub fn to_local(&self, a:&Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(a.dot(&self.axis[0]), a.dot(&self.axis[1]), a.dot(&self.axis[2]))
+    }
+
     pub fn build_from_w(&mut self, w:&Vector3<f64>) {
         let unit_w = w.normalize();
         let a = if unit_w.x.abs() > 0.9 {Vector3::new(0.0,1.0,0.0)} else {Vector3::new(1.0,0.0,0.0)};
@@ -41,4 +46,17 @@ impl Onb {
         self.axis[2] = unit_w;
     }
 
+    pub fn build_from_w_tangent(&mut self, w:&Vector3<f64>, tangent:&Vector3<f64>) {
+        let unit_w = w.normalize();
+        let u = tangent - unit_w * unit_w.dot(tangent);
+        if u.norm_squared() < 1e-16 {
+            self.build_from_w(w);
+            return;
+        }
+        let u = u.normalize();
+        self.axis[0] = u;
+        self.axis[1] = unit_w.cross(&u);
+        self.axis[2] = unit_w;
+    }
+
 }
\ No newline at end of file
@@ -2,13 +2,22 @@ use std::sync::Arc;
 
 use nalgebra::Vector3;
 
-use crate::{hittable::Hittable, onb::Onb, rtweekend::random_double, vec3::{random_cosine_direction, random_unit_vector}};
+use crate::{hittable::Hittable, microfacet::{gtr1_d, sample_gtr1, TrowbridgeReitz}, onb::Onb, rtweekend::random_double, vec3::{random_cosine_direction, random_unit_vector}};
 
 pub trait Pdf: Send + Sync {
     fn value(&self, direction:&Vector3<f64>) -> f64;
     fn generate(&self) -> Vector3<f64>;
 }
 
+pub fn power_heuristic(nf:i64, f_pdf:f64, ng:i64, g_pdf:f64) -> f64 {
+    let f = nf as f64 * f_pdf;
+    let g = ng as f64 * g_pdf;
+    if f * f + g * g <= 0.0 {
+        return 0.0;
+    }
+    (f * f) / (f * f + g * g)
+}
+
 pub struct SpherePdf {}
 
 impl SpherePdf {
@@ -77,23 +86,152 @@ impl Pdf for HittablePdf {
 }
 
 pub struct MixturePdf {
-    p:[Arc<dyn Pdf>;2],
+    p:Vec<Arc<dyn Pdf>>,
+    weights:Vec<f64>,
 }
 
 impl MixturePdf {
-    pub fn new (p0:Arc<dyn Pdf>, p1:Arc<dyn Pdf>) -> Self {
+    pub fn weighted (components:Vec<(f64, Arc<dyn Pdf>)>) -> Self {
+        let total:f64 = components.iter().map(|(w, _)| w.max(0.0)).sum();
+        let mut p = Vec::new();
+        let mut weights = Vec::new();
+        for (w, pdf) in components {
+            if w > 0.0 {
+                weights.push(w / total);
+                p.push(pdf);
+            }
+        }
         MixturePdf {
-            p:[p0.clone(),p1.clone()],
+            p,
+            weights,
         }
     }
 }
 
 impl Pdf for MixturePdf {
     fn value(&self, direction:&Vector3<f64>) -> f64 {
-        0.5 * self.p[0].value(&direction) + 0.5 * self.p[1].value(&direction)
+        self.p.iter().zip(self.weights.iter()).map(|(p, w)| w * p.value(&direction)).sum()
+    }
+
+    fn generate(&self) -> Vector3<f64> {
+        let u = random_double();
+        let mut cumulative = 0.0;
+        for (p, w) in self.p.iter().zip(self.weights.iter()) {
+            cumulative += w;
+            if u < cumulative {
+                return p.generate();
+            }
+        }
+        match self.p.last() {
+            Some(p) => p.generate(),
+            None => Vector3::zeros(),
+        }
+    }
+}
+
+pub struct MicrofacetReflectionPdf {
+    uvw:Onb,
+    wo:Vector3<f64>,
+    distribution:TrowbridgeReitz,
+}
+
+impl MicrofacetReflectionPdf {
+    pub fn new(uvw:Onb, wo:Vector3<f64>, distribution:TrowbridgeReitz) -> Self {
+        MicrofacetReflectionPdf {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetReflectionPdf {
+    fn value(&self, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi = self.uvw.to_local(&direction.normalize());
+        self.distribution.reflection_pdf(&self.wo, &wi)
+    }
+
+    fn generate(&self) -> Vector3<f64> {
+        let wm = self.distribution.sample_wm(&self.wo, random_double(), random_double());
+        let wi = -self.wo + 2.0 * self.wo.dot(&wm) * wm;
+        self.uvw.local_vec(&wi)
+    }
+}
+
+pub struct RoughDielectricPdf {
+    uvw:Onb,
+    wo:Vector3<f64>,
+    eta:f64,
+    distribution:TrowbridgeReitz,
+}
+
+impl RoughDielectricPdf {
+    pub fn new(uvw:Onb, wo:Vector3<f64>, eta:f64, distribution:TrowbridgeReitz) -> Self {
+        RoughDielectricPdf {
+            uvw,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for RoughDielectricPdf {
+    fn value(&self, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi = self.uvw.to_local(&direction.normalize());
+        self.distribution.dielectric_pdf(&self.wo, &wi, self.eta)
+    }
+
+    fn generate(&self) -> Vector3<f64> {
+        match self.distribution.sample_dielectric(&self.wo, self.eta, random_double(), random_double(), random_double()) {
+            Some(wi) => self.uvw.local_vec(&wi),
+            None => Vector3::zeros(),
+        }
+    }
+}
+
+pub struct ClearcoatPdf {
+    uvw:Onb,
+    wo:Vector3<f64>,
+    alpha:f64,
+}
+
+impl ClearcoatPdf {
+    pub fn new(uvw:Onb, wo:Vector3<f64>, alpha:f64) -> Self {
+        ClearcoatPdf {
+            uvw,
+            wo,
+            alpha,
+        }
+    }
+
+    pub fn local_value(wo:&Vector3<f64>, wi:&Vector3<f64>, alpha:f64) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (wo + wi).normalize();
+        gtr1_d(wm.z, alpha) * wm.z / (4.0 * wo.dot(&wm).abs())
+    }
+}
+
+impl Pdf for ClearcoatPdf {
+    fn value(&self, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi = self.uvw.to_local(&direction.normalize());
+        Self::local_value(&self.wo, &wi, self.alpha)
     }
 
     fn generate(&self) -> Vector3<f64> {
-        if random_double() < 0.5 { self.p[0].generate() } else { self.p[1].generate() }
+        let wm = sample_gtr1(self.alpha, random_double(), random_double());
+        let wi = -self.wo + 2.0 * self.wo.dot(&wm) * wm;
+        self.uvw.local_vec(&wi)
     }
 }
\ No newline at end of file
@@ -1,5 +1,4 @@
 use nalgebra::{ComplexField, Vector3};
-use rand::random;
 
 use crate::rtweekend::{random_double, random_int};
 use crate::vec3::vec3_rand;
@@ -41,7 +40,7 @@ impl Perlin {
         for di in 0..2 {
             for dj in 0..2 {
                 for dk in 0..2 {
-                    c[di][dj][dk] = self.randvec[(self.perm_x[(i as usize +di) & 255] ^ self.perm_y[(j as usize +dj) & 255] ^ self.perm_z[(k as usize +dk) & 255]) as usize];
+                    c[di][dj][dk] = self.randvec[(self.perm_x[((i + di as i64) & 255) as usize] ^ self.perm_y[((j + dj as i64) & 255) as usize] ^ self.perm_z[((k + dk as i64) & 255) as usize]) as usize];
                 }
             }
         }
@@ -0,0 +1,181 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::{gtr1_d, schlick_weight, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::pdf::{ClearcoatPdf, CosinePdf, MicrofacetReflectionPdf, MixturePdf, Pdf, RoughDielectricPdf};
+use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
+
+pub struct Principled {
+    pub base_color:Arc<dyn Texture>,
+    pub metallic:Arc<dyn Texture>,
+    pub roughness:Arc<dyn Texture>,
+    pub specular:Arc<dyn Texture>,
+    pub specular_tint:Arc<dyn Texture>,
+    pub sheen:Arc<dyn Texture>,
+    pub clearcoat:Arc<dyn Texture>,
+    pub clearcoat_gloss:Arc<dyn Texture>,
+    pub transmission:Arc<dyn Texture>,
+    pub ior:Arc<dyn Texture>,
+}
+
+struct Lobes {
+    uvw:Onb,
+    wo:Vector3<f64>,
+    base_color:Vector3<f64>,
+    tint:Vector3<f64>,
+    metallic:f64,
+    roughness:f64,
+    specular:f64,
+    specular_tint:f64,
+    sheen:f64,
+    clearcoat_alpha:f64,
+    eta:f64,
+    distribution:TrowbridgeReitz,
+    diffuse_weight:f64,
+    specular_weight:f64,
+    transmission_weight:f64,
+    clearcoat_weight:f64,
+}
+
+fn constant(value:f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Vector3::new(value, value, value)))
+}
+
+impl Principled {
+    pub fn initial (base_color:Arc<dyn Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic:constant(0.0),
+            roughness:constant(0.5),
+            specular:constant(0.5),
+            specular_tint:constant(0.0),
+            sheen:constant(0.0),
+            clearcoat:constant(0.0),
+            clearcoat_gloss:constant(1.0),
+            transmission:constant(0.0),
+            ior:constant(1.5),
+        }
+    }
+
+    fn lobes(&self, r_in:&Ray, rec:&HitRecord) -> Lobes {
+        let scalar = |tex:&Arc<dyn Texture>| tex.value(rec.u, rec.v, &rec.p).x.clamp(0.0, 1.0);
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let luminance = 0.3 * base_color.x + 0.6 * base_color.y + 0.1 * base_color.z;
+        let tint = if luminance > 0.0 {base_color / luminance} else {Vector3::new(1.0,1.0,1.0)};
+
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let transmission = scalar(&self.transmission);
+        let clearcoat = scalar(&self.clearcoat);
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness).max(2e-3);
+        let ior = self.ior.value(rec.u, rec.v, &rec.p).x.max(1e-3);
+
+        let mut uvw = Onb::new();
+        uvw.build_from_w(&rec.normal);
+        let wo = uvw.to_local(&-r_in.direction().normalize());
+
+        Lobes {
+            uvw,
+            wo,
+            base_color,
+            tint,
+            metallic,
+            roughness,
+            specular:scalar(&self.specular),
+            specular_tint:scalar(&self.specular_tint),
+            sheen:scalar(&self.sheen),
+            clearcoat_alpha:0.1 + (0.001 - 0.1) * scalar(&self.clearcoat_gloss),
+            eta:if rec.front_face {ior} else {1.0 / ior},
+            distribution:TrowbridgeReitz::new(alpha, alpha),
+            diffuse_weight:(1.0 - metallic) * (1.0 - transmission),
+            specular_weight:1.0 - transmission * (1.0 - metallic),
+            transmission_weight:transmission * (1.0 - metallic),
+            clearcoat_weight:0.25 * clearcoat,
+        }
+    }
+}
+
+impl Lobes {
+    fn f(&self, wi:&Vector3<f64>) -> Vector3<f64> {
+        let wo = &self.wo;
+        let mut f = Vector3::zeros();
+        if self.transmission_weight > 0.0 {
+            let ft = self.transmission_weight * self.distribution.dielectric_f(wo, wi, self.eta);
+            f += if wi.z < 0.0 {self.base_color * ft} else {Vector3::new(ft, ft, ft)};
+        }
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return f;
+        }
+        let wm = wo + wi;
+        if wm.norm_squared() == 0.0 {
+            return f;
+        }
+        let wm = wm.normalize();
+        let cos_d = wi.dot(&wm);
+        let fh = schlick_weight(cos_d);
+
+        if self.diffuse_weight > 0.0 {
+            let fd90 = 0.5 + 2.0 * cos_d * cos_d * self.roughness;
+            let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z)) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let white = Vector3::new(1.0,1.0,1.0);
+            let sheen_color = white + (self.tint - white) * 0.5;
+            f += self.diffuse_weight * (self.base_color * (fd / PI) + self.sheen * fh * sheen_color);
+        }
+
+        if self.specular_weight > 0.0 {
+            let white = Vector3::new(1.0,1.0,1.0);
+            let dielectric_f0 = 0.08 * self.specular * (white + (self.tint - white) * self.specular_tint);
+            let f0 = dielectric_f0 + (self.base_color - dielectric_f0) * self.metallic;
+            let fresnel = f0 + (white - f0) * fh;
+            let d = self.distribution.d(&wm);
+            let g = self.distribution.g(wo, wi);
+            f += self.specular_weight * fresnel * (d * g / (4.0 * wo.z * wi.z));
+        }
+
+        if self.clearcoat_weight > 0.0 {
+            let d = gtr1_d(wm.z, self.clearcoat_alpha);
+            let fresnel = 0.04 + 0.96 * fh;
+            let g = TrowbridgeReitz::new(0.25, 0.25).g(wo, wi);
+            let fc = self.clearcoat_weight * d * fresnel * g / (4.0 * wo.z * wi.z);
+            f += Vector3::new(fc, fc, fc);
+        }
+        f
+    }
+
+    fn pdf(&self) -> MixturePdf {
+        MixturePdf::weighted(vec![
+            (self.diffuse_weight, Arc::new(CosinePdf::new(&self.uvw.w())) as Arc<dyn Pdf>),
+            (self.specular_weight, Arc::new(MicrofacetReflectionPdf::new(self.uvw.clone(), self.wo, self.distribution))),
+            (self.transmission_weight, Arc::new(RoughDielectricPdf::new(self.uvw.clone(), self.wo, self.eta, self.distribution))),
+            (self.clearcoat_weight, Arc::new(ClearcoatPdf::new(self.uvw.clone(), self.wo, self.clearcoat_alpha))),
+        ])
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in:&Ray, rec:&HitRecord, srec:&mut ScatterRecord) -> bool {
+        let lobes = self.lobes(r_in, rec);
+        if lobes.wo.z <= 0.0 {
+            return false;
+        }
+        srec.attenuation = Vector3::new(1.0,1.0,1.0);
+        srec.pdf_ptr = Arc::new(lobes.pdf());
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in:&Ray, rec:&HitRecord, scattered:&Ray) -> f64 {
+        self.lobes(r_in, rec).pdf().value(&scattered.direction())
+    }
+
+    fn scattering_value(&self, r_in:&Ray, rec:&HitRecord, _srec:&ScatterRecord, scattered:&Ray) -> Vector3<f64> {
+        let lobes = self.lobes(r_in, rec);
+        let wi = lobes.uvw.to_local(&scattered.direction().normalize());
+        lobes.f(&wi) * wi.z.abs()
+    }
+}
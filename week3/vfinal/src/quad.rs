@@ -8,6 +8,7 @@ use crate::aabb::Aabb;
 use crate::interval::Interval;
 use crate::ray::Ray;
 use crate::rtweekend::random_double;
+use crate::light_sampler::{emitted_power, LightBounds};
 
 pub struct Quad {
     q:Vector3<f64>,
@@ -82,6 +83,8 @@ impl Hittable for Quad {
         rec.t = t;
         rec.p = intersection;
         rec.mat = self.mat.clone();
+        rec.dpdu = self.u;
+        rec.dpdv = self.v;
         rec.set_face_normal(r, &self.normal);
         true
     }
@@ -107,6 +110,18 @@ impl Hittable for Quad {
         let p = self.q + random_double() * self.u + random_double() * self.v;
         p - origin
     }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let center = self.q + 0.5 * (self.u + self.v);
+        let phi = emitted_power(&self.mat, &center, &self.normal, 0.5, 0.5, self.area);
+        Some(LightBounds::new(self.bbox.clone(), phi, self.normal, 1.0, 0.0, self.mat.is_two_sided()))
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        if self.mat.is_emissive() {
+            emitters.push(self);
+        }
+    }
 }
 
 pub fn abox(a:&Vector3<f64>, b:&Vector3<f64>, mat:&Arc<dyn Material>) -> Arc<HittableList> {
@@ -5,6 +5,7 @@ pub struct Ray{
     pub orig:Vector3<f64>,
     pub dir:Vector3<f64>,
     pub tm:f64,
+    pub wavelength:f64,
 }
 
 impl Ray {
@@ -13,6 +14,7 @@ impl Ray {
             orig: origin.clone(),
             dir: direction.clone(),
             tm: 0.0,
+            wavelength: 0.0,
         }
     }
     pub fn new_zero() -> Self {
@@ -20,6 +22,7 @@ impl Ray {
             orig: Vector3::zeros(),
             dir: Vector3::zeros(),
             tm: 0.0,
+            wavelength: 0.0,
         }
     }
     pub fn initial(origin:Vector3<f64>, direction:Vector3<f64>, time:f64) -> Self {
@@ -27,6 +30,7 @@ impl Ray {
             orig: origin.clone(),
             dir: direction.clone(),
             tm: time,
+            wavelength: 0.0,
         }
     }
     pub fn origin(&self) -> Vector3<f64> {
@@ -41,4 +45,8 @@ impl Ray {
     pub fn time(&self) -> f64 {
         self.tm
     }
+    pub fn with_wavelength(mut self, wavelength:f64) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
 }
\ No newline at end of file
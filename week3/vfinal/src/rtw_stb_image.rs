@@ -20,7 +20,6 @@ impl RtwImage {
 
     pub fn new (image_filename:&str) -> Self {
         let filename = image_filename.to_string();
-        let mut image = Self::initial();
         let search_path = [
             "images",
             "../images",
@@ -30,24 +29,23 @@ impl RtwImage {
             "../../../../../images",
             "../../../../../../images",
         ];
-        let mut found = false;
         for pre_path in search_path.iter() {
             let path = Path::new(pre_path).join(&filename);
-            if let Ok(img) = image::open(&path) {
-                let img = img.clone();
-                image = Self {
-                    image_width:img.width() as i64,
-                    image_height:img.height() as i64,
-                    data:Some(img),
-                };
-                found = true;
-                break;
+            if let Some(image) = Self::load(&path) {
+                return image;
             }
         }
-        if !found {
-            eprintln!("ERROR: Could not load image file '{}'.", image_filename);
-        }
-        image
+        eprintln!("ERROR: Could not load image file '{}'.", image_filename);
+        Self::initial()
+    }
+
+    pub fn load (path:&Path) -> Option<Self> {
+        let img = image::open(path).ok()?;
+        Some(Self {
+            image_width:img.width() as i64,
+            image_height:img.height() as i64,
+            data:Some(img),
+        })
     }
 
     pub fn clamp(x:i64, low:i64, high:i64) -> i64 {
@@ -79,4 +77,14 @@ impl RtwImage {
             Vector3::new(255, 0, 255)
         }
     }
+
+    pub fn alpha_data(&self, x:i64, y:i64) -> Option<i64> {
+        let img = self.data.as_ref()?;
+        if !img.color().has_alpha() {
+            return None;
+        }
+        let x = Self::clamp(x, 0, self.image_width);
+        let y = Self::clamp(y, 0, self.image_height);
+        Some(img.get_pixel(x as u32, y as u32)[3] as i64)
+    }
 }
\ No newline at end of file
@@ -1,7 +1,57 @@
 use nalgebra::Vector3;
+use std::cell::Cell;
 
-use rand::Rng;
-use rand::distributions::Uniform;
+#[derive(Clone, Copy)]
+pub struct Pcg32 {
+    state:u64,
+    inc:u64,
+}
+
+impl Pcg32 {
+    pub fn new(seed:u64, stream:u64) -> Self {
+        let mut rng = Pcg32 {
+            state:0,
+            inc:(stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let oldstate = self.state;
+        self.state = oldstate.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+        let xorshifted = (((oldstate >> 18) ^ oldstate) >> 27) as u32;
+        let rot = (oldstate >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        let bits = (((self.next_u32() as u64) << 32) | self.next_u32() as u64) >> 11;
+        bits as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+thread_local! {
+    static RNG:Cell<Pcg32> = const { Cell::new(Pcg32 { state:0x853c49e6748fea9b, inc:0xda3e39cb94b95bdb }) };
+}
+
+pub fn mix_seed(a:u64, b:u64) -> u64 {
+    let mut z = a ^ b.wrapping_add(0x9e3779b97f4a7c15).wrapping_add(a << 6).wrapping_add(a >> 2);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn seed_rng(seed:u64, stream:u64) {
+    RNG.with(|rng| rng.set(Pcg32::new(seed, stream)));
+}
+
+pub fn seed_sample(seed:u64, i:i64, j:i64, sample:i64) {
+    let stream = mix_seed(mix_seed(i as u64, j as u64), sample as u64);
+    seed_rng(seed, stream);
+}
 
 pub fn near_zero(v:&Vector3<f64>) -> bool {
     let s = 1e-8;
@@ -9,13 +59,16 @@ pub fn near_zero(v:&Vector3<f64>) -> bool {
 }
 
 pub fn random_double() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.sample(Uniform::new(0.0, 1.0))
+    RNG.with(|rng| {
+        let mut r = rng.get();
+        let x = r.next_f64();
+        rng.set(r);
+        x
+    })
 }
 
 pub fn random_f64(min:f64, max:f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.sample(Uniform::new(min, max))
+    min + (max - min) * random_double()
 }
 
 pub fn random_int(min:i64, max:i64) -> i64 {
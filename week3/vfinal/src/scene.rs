@@ -0,0 +1,1248 @@
+use nalgebra::{Matrix4, Vector3};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+use crate::bvh::{BvhBuilder, LinearBvh, SplitMethod};
+use crate::bump::ShadingNormal;
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::cutout::Cutout;
+use crate::density::{DensityField, TurbulenceDensity, VoxelGrid};
+use crate::emission::{CosinePower, EmissionProfile};
+use crate::environment::{Environment, EnvironmentLight, ImageEnvironment};
+use crate::hittable::{Hittable, RotateY, Transform, Translate};
+use crate::heterogeneous_medium::HeterogeneousMedium;
+use crate::hittable_list::HittableList;
+use crate::ies::IesProfile;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::light_sampler::{luminance, LightList, LightSampling};
+use crate::material::{absorption_from_transmittance, Conductor, Dielectric, Dispersion, DiffuseLight, Isotropic, Lambertian, Material, Metal, RoughDielectric};
+use crate::obj::load_obj;
+use crate::principled::Principled;
+use crate::quad::{abox, Quad};
+use crate::rtweekend::seed_rng;
+use crate::sky::{sun_direction, Sky};
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::triangle::Triangle;
+
+pub struct Scene {
+    pub camera:Camera,
+    pub world:Arc<dyn Hittable>,
+    pub lights:Arc<dyn Hittable>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64;3]),
+    Value(f64),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color:[f64;3] },
+    Checker { scale:f64, even:TextureRef, odd:TextureRef },
+    Image { file:String },
+    Noise { #[serde(default = "default_noise_scale")] scale:f64 },
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+fn default_modifier_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo:TextureRef },
+    Metal { albedo:[f64;3], #[serde(default)] fuzz:f64 },
+    Conductor {
+        preset:Option<String>,
+        eta:Option<[f64;3]>,
+        k:Option<[f64;3]>,
+        reflectance:Option<[f64;3]>,
+        #[serde(default)]
+        roughness:f64,
+        roughness_v:Option<f64>,
+    },
+    Dielectric {
+        refraction_index:Option<f64>,
+        dispersion:Option<DispersionDesc>,
+        #[serde(flatten)]
+        absorption:AbsorptionDesc,
+    },
+    RoughDielectric {
+        refraction_index:f64,
+        roughness:TextureRef,
+        #[serde(flatten)]
+        absorption:AbsorptionDesc,
+    },
+    Principled {
+        base_color:TextureRef,
+        metallic:Option<TextureRef>,
+        roughness:Option<TextureRef>,
+        specular:Option<TextureRef>,
+        specular_tint:Option<TextureRef>,
+        sheen:Option<TextureRef>,
+        clearcoat:Option<TextureRef>,
+        clearcoat_gloss:Option<TextureRef>,
+        transmission:Option<TextureRef>,
+        ior:Option<TextureRef>,
+    },
+    NormalMap {
+        material:String,
+        map:TextureRef,
+        #[serde(default = "default_modifier_scale")]
+        strength:f64,
+    },
+    Bump {
+        material:String,
+        height:TextureRef,
+        #[serde(default = "default_modifier_scale")]
+        scale:f64,
+    },
+    DiffuseLight {
+        emit:TextureRef,
+        #[serde(default)]
+        two_sided:bool,
+        profile:Option<EmissionProfileDesc>,
+        power:Option<f64>,
+    },
+    Isotropic { albedo:TextureRef },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EmissionProfileDesc {
+    CosinePower { exponent:f64 },
+    Ies {
+        file:String,
+        #[serde(default)]
+        photometric:bool,
+        lumens:Option<f64>,
+    },
+}
+
+struct Profile {
+    shape:Arc<dyn EmissionProfile>,
+    // Peak candela and total flux of a photometric IES file, otherwise the
+    // profile only shapes the light's own intensity.
+    photometry:Option<(f64, f64)>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DispersionDesc {
+    Preset(String),
+    Cauchy { a:f64, b:f64 },
+    Sellmeier { b:[f64;3], c:[f64;3] },
+}
+
+impl DispersionDesc {
+    fn dispersion(&self) -> Result<Dispersion, String> {
+        match self {
+            DispersionDesc::Preset(name) => Dispersion::preset(name).ok_or_else(|| format!("unknown dispersion preset '{}'", name)),
+            DispersionDesc::Cauchy { a, b } => Ok(Dispersion::Cauchy { a:*a, b:*b }),
+            DispersionDesc::Sellmeier { b, c } => Ok(Dispersion::Sellmeier { b:*b, c:*c }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AbsorptionDesc {
+    absorption:Option<[f64;3]>,
+    transmittance:Option<[f64;3]>,
+    #[serde(default = "default_transmittance_distance")]
+    transmittance_distance:f64,
+}
+
+fn default_transmittance_distance() -> f64 {
+    1.0
+}
+
+impl AbsorptionDesc {
+    fn coefficient(&self) -> Result<Vector3<f64>, String> {
+        match (self.absorption, self.transmittance) {
+            (Some(_), Some(_)) => Err("give either 'absorption' or 'transmittance', not both".to_string()),
+            (Some(a), None) => Ok(to_vec3(&a)),
+            (None, Some(t)) => {
+                if self.transmittance_distance <= 0.0 {
+                    return Err("'transmittance_distance' must be positive".to_string());
+                }
+                Ok(absorption_from_transmittance(to_vec3(&t), self.transmittance_distance))
+            }
+            (None, None) => Ok(Vector3::zeros()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate([f64;3]),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate { axis:[f64;3], angle:f64 },
+    Scale(ScaleDesc),
+    Matrix([[f64;4];4]),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes([f64;3]),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere { center:[f64;3], center2:Option<[f64;3]>, radius:f64, material:Option<String> },
+    Quad { q:[f64;3], u:[f64;3], v:[f64;3], material:Option<String> },
+    Box { a:[f64;3], b:[f64;3], material:Option<String> },
+    Triangle { a:[f64;3], b:[f64;3], c:[f64;3], material:Option<String> },
+    Mesh { file:String, material:Option<String> },
+    ConstantMedium { boundary:Box<ObjectDesc>, density:f64, albedo:TextureRef },
+    HeterogeneousMedium {
+        boundary:Box<ObjectDesc>,
+        density:DensityDesc,
+        sigma_a:f64,
+        sigma_s:f64,
+        #[serde(default = "default_medium_albedo")]
+        albedo:TextureRef,
+    },
+    Group { objects:Vec<ObjectDesc>, #[serde(default)] bvh:bool },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum DensityDesc {
+    Turbulence {
+        #[serde(default = "default_noise_scale")]
+        scale:f64,
+        #[serde(default = "default_turbulence_depth")]
+        depth:i64,
+    },
+    Voxels { file:String },
+}
+
+fn default_turbulence_depth() -> i64 {
+    7
+}
+
+fn default_medium_albedo() -> TextureRef {
+    TextureRef::Value(1.0)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CutoutDesc {
+    alpha:TextureRef,
+    #[serde(default = "default_alpha_threshold")]
+    threshold:f64,
+    #[serde(default)]
+    stochastic:bool,
+}
+
+fn default_alpha_threshold() -> f64 {
+    0.5
+}
+
+struct ObjectDesc {
+    shape:ShapeDesc,
+    cutout:Option<CutoutDesc>,
+    transform:Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectOptions {
+    cutout:Option<CutoutDesc>,
+    #[serde(default)]
+    transform:Vec<TransformDesc>,
+}
+
+// serde ignores deny_unknown_fields across #[serde(flatten)], so tables that
+// mix a tagged enum with shared keys are split by hand and each half is
+// deserialized strictly.
+fn split_table<'de, D:Deserializer<'de>>(deserializer:D, keys:&[&str]) -> Result<(toml::Value, toml::Value), D::Error> {
+    let mut table = toml::Table::deserialize(deserializer)?;
+    let mut shared = toml::Table::new();
+    for key in keys {
+        if let Some(value) = table.remove(*key) {
+            shared.insert(key.to_string(), value);
+        }
+    }
+    Ok((toml::Value::Table(table), toml::Value::Table(shared)))
+}
+
+impl<'de> Deserialize<'de> for ObjectDesc {
+    fn deserialize<D:Deserializer<'de>>(deserializer:D) -> Result<Self, D::Error> {
+        let (shape, options) = split_table(deserializer, &["cutout", "transform"])?;
+        let shape = ShapeDesc::deserialize(shape).map_err(D::Error::custom)?;
+        let options = ObjectOptions::deserialize(options).map_err(D::Error::custom)?;
+        Ok(ObjectDesc {
+            shape,
+            cutout:options.cutout,
+            transform:options.transform,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentSource {
+    Image {
+        file:String,
+        #[serde(default = "default_environment_intensity")]
+        intensity:f64,
+        #[serde(default)]
+        rotation:f64,
+    },
+    Sky {
+        sun_direction:Option<[f64;3]>,
+        #[serde(default = "default_sun_elevation")]
+        sun_elevation:f64,
+        #[serde(default)]
+        sun_azimuth:f64,
+        #[serde(default = "default_turbidity")]
+        turbidity:f64,
+        #[serde(default = "default_environment_intensity")]
+        intensity:f64,
+        #[serde(default = "default_environment_intensity")]
+        sun_intensity:f64,
+        #[serde(default = "default_sun_size")]
+        sun_size:f64,
+        #[serde(default)]
+        ground:[f64;3],
+    },
+}
+
+fn default_environment_intensity() -> f64 {
+    1.0
+}
+
+fn default_sun_elevation() -> f64 {
+    45.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_sun_size() -> f64 {
+    0.533
+}
+
+struct EnvironmentDesc {
+    source:EnvironmentSource,
+    sample:bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentOptions {
+    #[serde(default = "default_environment_sample")]
+    sample:bool,
+}
+
+impl<'de> Deserialize<'de> for EnvironmentDesc {
+    fn deserialize<D:Deserializer<'de>>(deserializer:D) -> Result<Self, D::Error> {
+        let (source, options) = split_table(deserializer, &["sample"])?;
+        let source = EnvironmentSource::deserialize(source).map_err(D::Error::custom)?;
+        let options = EnvironmentOptions::deserialize(options).map_err(D::Error::custom)?;
+        Ok(EnvironmentDesc {
+            source,
+            sample:options.sample,
+        })
+    }
+}
+
+fn default_environment_sample() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum DeltaLightDesc {
+    Point {
+        position:[f64;3],
+        intensity:[f64;3],
+        #[serde(default = "default_point_direction")]
+        direction:[f64;3],
+        profile:Option<EmissionProfileDesc>,
+    },
+    Spot {
+        position:[f64;3],
+        direction:[f64;3],
+        intensity:[f64;3],
+        angle:f64,
+        falloff_start:Option<f64>,
+        profile:Option<EmissionProfileDesc>,
+    },
+    Directional { direction:[f64;3], irradiance:[f64;3] },
+}
+
+fn default_point_direction() -> [f64;3] {
+    [0.0, -1.0, 0.0]
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum LightSamplerDesc {
+    #[default]
+    Uniform,
+    Power,
+    Bvh,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum BvhSplitDesc {
+    #[default]
+    Sah,
+    Middle,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera:BTreeMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    textures:BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials:BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    objects:Vec<Spanned<ObjectDesc>>,
+    #[serde(default)]
+    lights:Vec<Spanned<ObjectDesc>>,
+    #[serde(default = "default_auto_lights")]
+    auto_lights:bool,
+    environment:Option<Spanned<EnvironmentDesc>>,
+    #[serde(default)]
+    delta_lights:Vec<Spanned<DeltaLightDesc>>,
+    #[serde(default)]
+    light_sampler:LightSamplerDesc,
+    #[serde(default)]
+    bvh:bool,
+    #[serde(default)]
+    bvh_split:BvhSplitDesc,
+}
+
+fn default_auto_lights() -> bool {
+    true
+}
+
+fn to_vec3(v:&[f64;3]) -> Vector3<f64> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn value_f64(key:&str, value:&toml::Value) -> Result<f64, String> {
+    match value {
+        toml::Value::Float(f) => Ok(*f),
+        toml::Value::Integer(i) => Ok(*i as f64),
+        _ => Err(format!("camera field '{}' expects a number", key)),
+    }
+}
+
+fn value_i64(key:&str, value:&toml::Value) -> Result<i64, String> {
+    match value {
+        toml::Value::Integer(i) => Ok(*i),
+        _ => Err(format!("camera field '{}' expects an integer", key)),
+    }
+}
+
+fn value_at_least(key:&str, value:&toml::Value, min:i64) -> Result<i64, String> {
+    let v = value_i64(key, value)?;
+    if v < min {
+        return Err(format!("camera field '{}' must be at least {}, got {}", key, min, v));
+    }
+    Ok(v)
+}
+
+fn value_vec3(key:&str, value:&toml::Value) -> Result<Vector3<f64>, String> {
+    match value {
+        toml::Value::Array(a) if a.len() == 3 => {
+            Ok(Vector3::new(value_f64(key, &a[0])?, value_f64(key, &a[1])?, value_f64(key, &a[2])?))
+        }
+        _ => Err(format!("camera field '{}' expects an array of 3 numbers", key)),
+    }
+}
+
+pub fn set_camera_field(cam:&mut Camera, key:&str, value:&toml::Value) -> Result<(), String> {
+    match key {
+        "aspect_ratio" => cam.aspect_ratio = value_f64(key, value)?,
+        "image_width" => cam.image_width = value_at_least(key, value, 1)?,
+        "samples_per_pixel" => cam.samples_per_pixel = value_at_least(key, value, 1)?,
+        "max_depth" => cam.max_depth = value_at_least(key, value, 1)?,
+        "background" => cam.background = value_vec3(key, value)?,
+        "vfov" => cam.vfov = value_f64(key, value)?,
+        "lookfrom" => cam.lookfrom = value_vec3(key, value)?,
+        "lookat" => cam.lookat = value_vec3(key, value)?,
+        "vup" => cam.vup = value_vec3(key, value)?,
+        "defocus_angle" => cam.defocus_angle = value_f64(key, value)?,
+        "focus_dist" => cam.focus_dist = value_f64(key, value)?,
+        "output_file" => match value {
+            toml::Value::String(s) => cam.output_file = s.clone(),
+            _ => return Err(format!("camera field '{}' expects a string", key)),
+        },
+        "bit_depth" => cam.bit_depth = value_i64(key, value)?,
+        "threads" => cam.threads = value_at_least(key, value, 1)?,
+        "tile_size" => cam.tile_size = value_at_least(key, value, 1)?,
+        "seed" => cam.seed = value_at_least(key, value, 0)? as u64,
+        "spectral" => match value {
+            toml::Value::Boolean(b) => cam.spectral = *b,
+            _ => return Err(format!("camera field '{}' expects a boolean", key)),
+        },
+        _ => return Err(format!("unknown camera field '{}'", key)),
+    }
+    Ok(())
+}
+
+struct SceneBuilder<'a> {
+    filename:&'a str,
+    source:&'a str,
+    dir:PathBuf,
+    desc:&'a SceneDesc,
+    textures:HashMap<String, Arc<dyn Texture>>,
+    materials:HashMap<String, Arc<dyn Material>>,
+    resolving:HashSet<String>,
+    resolving_materials:HashSet<String>,
+    bvh:BvhBuilder,
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn error(&self, span:std::ops::Range<usize>, message:String) -> String {
+        let line = self.source[..span.start.min(self.source.len())].matches('\n').count() + 1;
+        format!("{}:{}: {}", self.filename, line, message)
+    }
+
+    fn texture_ref(&mut self, tex:&TextureRef) -> Result<Arc<dyn Texture>, String> {
+        match tex {
+            TextureRef::Color(c) => Ok(Arc::new(SolidColor::new(to_vec3(c)))),
+            TextureRef::Value(v) => Ok(Arc::new(SolidColor::new(Vector3::new(*v, *v, *v)))),
+            TextureRef::Name(name) => self.texture(name),
+        }
+    }
+
+    fn texture(&mut self, name:&str) -> Result<Arc<dyn Texture>, String> {
+        if let Some(tex) = self.textures.get(name) {
+            return Ok(tex.clone());
+        }
+        let desc = self.desc;
+        let entry = desc.textures.get(name).ok_or_else(|| format!("unknown texture '{}'", name))?;
+        if !self.resolving.insert(name.to_string()) {
+            return Err(self.error(entry.span(), format!("texture '{}' refers to itself", name)));
+        }
+        let tex:Arc<dyn Texture> = match entry.get_ref() {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(to_vec3(color))),
+            TextureDesc::Checker { scale, even, odd } => {
+                let even = self.texture_ref(even).map_err(|e| self.error(entry.span(), e))?;
+                let odd = self.texture_ref(odd).map_err(|e| self.error(entry.span(), e))?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDesc::Image { file } => match ImageTexture::from_path(&self.dir.join(file)) {
+                Some(tex) => Arc::new(tex),
+                None => return Err(self.error(entry.span(), format!("could not load image '{}'", file))),
+            },
+            TextureDesc::Noise { scale } => Arc::new(NoiseTexture::initial(*scale)),
+        };
+        self.resolving.remove(name);
+        self.textures.insert(name.to_string(), tex.clone());
+        Ok(tex)
+    }
+
+    fn material(&mut self, name:&str) -> Result<Arc<dyn Material>, String> {
+        if let Some(mat) = self.materials.get(name) {
+            return Ok(mat.clone());
+        }
+        let desc = self.desc;
+        let entry = desc.materials.get(name).ok_or_else(|| format!("unknown material '{}'", name))?;
+        if !self.resolving_materials.insert(name.to_string()) {
+            return Err(self.error(entry.span(), format!("material '{}' refers to itself", name)));
+        }
+        let mat:Arc<dyn Material> = match entry.get_ref() {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::initial(self.texture_ref(albedo).map_err(|e| self.error(entry.span(), e))?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(to_vec3(albedo), *fuzz)),
+            MaterialDesc::Conductor { preset, eta, k, reflectance, roughness, roughness_v } => {
+                let roughness_v = roughness_v.unwrap_or(*roughness);
+                let mut conductor = match (preset, eta, k, reflectance) {
+                    (Some(name), None, None, None) => Conductor::preset(name, *roughness)
+                        .ok_or_else(|| self.error(entry.span(), format!("unknown conductor preset '{}'", name)))?,
+                    (None, Some(eta), Some(k), None) => Conductor::new(to_vec3(eta), to_vec3(k), *roughness),
+                    (None, None, None, Some(r)) => Conductor::from_reflectance(to_vec3(r), *roughness),
+                    _ => return Err(self.error(entry.span(),
+                        "conductor needs exactly one of 'preset', 'eta' with 'k', or 'reflectance'".to_string())),
+                };
+                conductor.roughness_v = roughness_v.clamp(0.0, 1.0);
+                Arc::new(conductor)
+            }
+            MaterialDesc::Dielectric { refraction_index, dispersion, absorption } => {
+                let absorption = absorption.coefficient().map_err(|e| self.error(entry.span(), e))?;
+                match (refraction_index, dispersion) {
+                    (Some(ri), None) => Arc::new(Dielectric::with_absorption(*ri, absorption)),
+                    (None, Some(dispersion)) => {
+                        let dispersion = dispersion.dispersion().map_err(|e| self.error(entry.span(), e))?;
+                        Arc::new(Dielectric::with_dispersion(dispersion, absorption))
+                    }
+                    _ => return Err(self.error(entry.span(),
+                        "dielectric needs exactly one of 'refraction_index' or 'dispersion'".to_string())),
+                }
+            }
+            MaterialDesc::RoughDielectric { refraction_index, roughness, absorption } => {
+                let absorption = absorption.coefficient().map_err(|e| self.error(entry.span(), e))?;
+                let roughness = self.texture_ref(roughness).map_err(|e| self.error(entry.span(), e))?;
+                let mut mat = RoughDielectric::with_texture(*refraction_index, roughness);
+                mat.absorption = absorption;
+                Arc::new(mat)
+            }
+            MaterialDesc::Principled {
+                base_color, metallic, roughness, specular, specular_tint,
+                sheen, clearcoat, clearcoat_gloss, transmission, ior,
+            } => {
+                let base_color = self.texture_ref(base_color).map_err(|e| self.error(entry.span(), e))?;
+                let mut mat = Principled::initial(base_color);
+                let params = [
+                    (metallic, &mut mat.metallic),
+                    (roughness, &mut mat.roughness),
+                    (specular, &mut mat.specular),
+                    (specular_tint, &mut mat.specular_tint),
+                    (sheen, &mut mat.sheen),
+                    (clearcoat, &mut mat.clearcoat),
+                    (clearcoat_gloss, &mut mat.clearcoat_gloss),
+                    (transmission, &mut mat.transmission),
+                    (ior, &mut mat.ior),
+                ];
+                for (desc, param) in params {
+                    if let Some(tex) = desc {
+                        *param = self.texture_ref(tex).map_err(|e| self.error(entry.span(), e))?;
+                    }
+                }
+                Arc::new(mat)
+            }
+            MaterialDesc::NormalMap { material, map, strength } => {
+                let inner = self.inner_material(material, entry.span())?;
+                let map = self.texture_ref(map).map_err(|e| self.error(entry.span(), e))?;
+                Arc::new(ShadingNormal::normal_map(inner, map, *strength))
+            }
+            MaterialDesc::Bump { material, height, scale } => {
+                let inner = self.inner_material(material, entry.span())?;
+                let height = self.texture_ref(height).map_err(|e| self.error(entry.span(), e))?;
+                Arc::new(ShadingNormal::bump(inner, height, *scale))
+            }
+            MaterialDesc::DiffuseLight { emit, two_sided, profile, power } => {
+                if power.is_some() {
+                    return Err(self.error(entry.span(), format!(
+                        "material '{}' gives 'power', which only applies when an object uses it directly", name)));
+                }
+                let (light, flux) = self.diffuse_light(emit, *two_sided, profile).map_err(|e| self.error(entry.span(), e))?;
+                if flux.is_some() {
+                    return Err(self.error(entry.span(), format!(
+                        "material '{}' uses a photometric profile, which only applies when an object uses it directly", name)));
+                }
+                Arc::new(light)
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::new(self.texture_ref(albedo).map_err(|e| self.error(entry.span(), e))?))
+            }
+        };
+        self.resolving_materials.remove(name);
+        self.materials.insert(name.to_string(), mat.clone());
+        Ok(mat)
+    }
+
+    fn inner_material(&mut self, name:&str, span:std::ops::Range<usize>) -> Result<Arc<dyn Material>, String> {
+        if !self.desc.materials.contains_key(name) {
+            return Err(self.error(span, format!("unknown material '{}'", name)));
+        }
+        self.material(name)
+    }
+
+    fn emission_profile(&mut self, desc:&EmissionProfileDesc) -> Result<Profile, String> {
+        match desc {
+            EmissionProfileDesc::CosinePower { exponent } => {
+                if *exponent < 0.0 {
+                    return Err("cosine power 'exponent' must not be negative".to_string());
+                }
+                Ok(Profile { shape:Arc::new(CosinePower::new(*exponent)), photometry:None })
+            }
+            EmissionProfileDesc::Ies { file, photometric, lumens } => {
+                let ies = IesProfile::load(&self.dir.join(file))?;
+                // Relative photometry is measured for the rated lamp output, so a
+                // different lamp scales every candela value by the lumen ratio.
+                let scale = match lumens {
+                    None => 1.0,
+                    Some(_) if !photometric => return Err(format!("IES profile '{}' gives 'lumens' without 'photometric = true'", file)),
+                    Some(lumens) if *lumens < 0.0 => return Err(format!("IES profile '{}' has negative 'lumens'", file)),
+                    Some(lumens) => match ies.lumens {
+                        Some(rated) => lumens / rated,
+                        None => return Err(format!("IES profile '{}' uses absolute photometry, so 'lumens' cannot rescale it", file)),
+                    },
+                };
+                let photometry = if *photometric {Some((ies.max_candela() * scale, ies.flux() * scale))} else {None};
+                Ok(Profile { shape:Arc::new(ies), photometry })
+            }
+        }
+    }
+
+    fn density_field(&mut self, desc:&DensityDesc) -> Result<Arc<dyn DensityField>, String> {
+        match desc {
+            DensityDesc::Turbulence { scale, depth } => {
+                if *depth < 1 {
+                    return Err("turbulence density 'depth' must be at least 1".to_string());
+                }
+                Ok(Arc::new(TurbulenceDensity::new(*scale, *depth)))
+            }
+            DensityDesc::Voxels { file } => Ok(Arc::new(VoxelGrid::load(&self.dir.join(file))?)),
+        }
+    }
+
+    fn diffuse_light(&mut self, emit:&TextureRef, two_sided:bool, profile:&Option<EmissionProfileDesc>) -> Result<(DiffuseLight, Option<f64>), String> {
+        let mut light = DiffuseLight::new(self.texture_ref(emit)?);
+        light.two_sided = two_sided;
+        let mut flux = None;
+        if let Some(profile) = profile {
+            let profile = self.emission_profile(profile)?;
+            flux = profile.photometry.map(|(_, flux)| flux);
+            light.profile = Some(profile.shape);
+        }
+        Ok((light, flux))
+    }
+
+    fn emit_luminance(&self, emit:&TextureRef) -> Option<f64> {
+        let color = match emit {
+            TextureRef::Color(c) => to_vec3(c),
+            TextureRef::Value(v) => Vector3::new(*v, *v, *v),
+            TextureRef::Name(name) => match self.desc.textures.get(name).map(Spanned::get_ref) {
+                Some(TextureDesc::Solid { color }) => to_vec3(color),
+                _ => return None,
+            },
+        };
+        Some(luminance(&color)).filter(|l| *l > 0.0)
+    }
+
+    fn object_material(&mut self, material:&Option<String>, is_light:bool, area:Option<f64>) -> Result<Arc<dyn Material>, String> {
+        let name = match material {
+            Some(name) => name,
+            None if is_light => return Ok(Arc::new(DiffuseLight::initial(Vector3::zeros()))),
+            None => return Err(String::from("object has no material")),
+        };
+        let desc = self.desc;
+        match desc.materials.get(name).map(Spanned::get_ref) {
+            Some(MaterialDesc::DiffuseLight { emit, two_sided, profile, power }) if power.is_some() || is_photometric(profile) => {
+                if power.is_some_and(|p| p < 0.0) {
+                    return Err(format!("material '{}' has a negative 'power'", name));
+                }
+                let area = area.ok_or_else(|| format!(
+                    "material '{}' gives 'power' or a photometric profile, which needs an unscaled sphere, quad, box or triangle", name))?;
+                let tint = self.emit_luminance(emit).ok_or_else(|| format!(
+                    "material '{}' gives 'power' or a photometric profile, which needs a constant, non-black 'emit' color", name))?;
+                let (mut light, flux) = self.diffuse_light(emit, *two_sided, profile)?;
+                // An explicit power overrides the flux from the IES file, and
+                // 'emit' only tints the light so its luminance is divided out.
+                light.set_power(power.or(flux).unwrap_or(0.0) / tint, area);
+                Ok(Arc::new(light))
+            }
+            _ => self.material(name),
+        }
+    }
+
+    fn object(&mut self, desc:&ObjectDesc, is_light:bool, area_scale:Option<f64>) -> Result<Arc<dyn Hittable>, String> {
+        let area_scale = area_scale.and_then(|s| transform_area_scale(&desc.transform).map(|t| s * t));
+        let mut object:Arc<dyn Hittable> = match &desc.shape {
+            ShapeDesc::Sphere { center, center2, radius, material } => {
+                let area = 4.0 * std::f64::consts::PI * radius * radius;
+                let mat = self.object_material(material, is_light, area_scale.map(|s| s * area))?;
+                match center2 {
+                    Some(center2) => Arc::new(Sphere::initial(to_vec3(center), to_vec3(center2), *radius, mat)),
+                    None => Arc::new(Sphere::new(to_vec3(center), *radius, mat)),
+                }
+            }
+            ShapeDesc::Quad { q, u, v, material } => {
+                let area = to_vec3(u).cross(&to_vec3(v)).norm();
+                let mat = self.object_material(material, is_light, area_scale.map(|s| s * area))?;
+                Arc::new(Quad::new(to_vec3(q), to_vec3(u), to_vec3(v), mat))
+            }
+            ShapeDesc::Box { a, b, material } => {
+                let d = (to_vec3(b) - to_vec3(a)).abs();
+                let area = 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+                let mat = self.object_material(material, is_light, area_scale.map(|s| s * area))?;
+                abox(&to_vec3(a), &to_vec3(b), &mat)
+            }
+            ShapeDesc::Triangle { a, b, c, material } => {
+                let area = 0.5 * (to_vec3(b) - to_vec3(a)).cross(&(to_vec3(c) - to_vec3(a))).norm();
+                let mat = self.object_material(material, is_light, area_scale.map(|s| s * area))?;
+                Arc::new(Triangle::new(to_vec3(a), to_vec3(b), to_vec3(c), mat))
+            }
+            ShapeDesc::Mesh { file, material } => {
+                let mat = self.object_material(material, is_light, None)?;
+                let path = self.dir.join(file);
+                Arc::new(load_obj(&path.to_string_lossy(), mat)?)
+            }
+            ShapeDesc::ConstantMedium { boundary, density, albedo } => {
+                let boundary = self.object(boundary, true, area_scale)?;
+                Arc::new(ConstantMedium::new(boundary, *density, self.texture_ref(albedo)?))
+            }
+            ShapeDesc::HeterogeneousMedium { boundary, density, sigma_a, sigma_s, albedo } => {
+                if *sigma_a < 0.0 || *sigma_s < 0.0 {
+                    return Err("heterogeneous medium 'sigma_a' and 'sigma_s' must not be negative".to_string());
+                }
+                let boundary = self.object(boundary, true, area_scale)?;
+                let density = self.density_field(density)?;
+                Arc::new(HeterogeneousMedium::new(boundary, density, *sigma_a, *sigma_s, self.texture_ref(albedo)?))
+            }
+            ShapeDesc::Group { objects, bvh } => {
+                let mut list = HittableList::new();
+                for object in objects {
+                    list.add(self.object(object, is_light, area_scale)?);
+                }
+                if *bvh && !list.objects.is_empty() {
+                    Arc::new(LinearBvh::new(&self.bvh, &list.objects))
+                } else {
+                    Arc::new(list)
+                }
+            }
+        };
+        if let Some(cutout) = &desc.cutout {
+            let alpha = self.texture_ref(&cutout.alpha)?;
+            object = if cutout.stochastic {
+                Arc::new(Cutout::stochastic(object, alpha))
+            } else {
+                Arc::new(Cutout::new(object, alpha, cutout.threshold))
+            };
+        }
+        for transform in desc.transform.iter() {
+            object = match transform {
+                TransformDesc::Translate(offset) => Arc::new(Translate::new(object, to_vec3(offset))),
+                TransformDesc::RotateY(angle) => Arc::new(RotateY::new(object, *angle)),
+                TransformDesc::RotateX(angle) => Arc::new(Transform::rotation(object, Vector3::x(), *angle)),
+                TransformDesc::RotateZ(angle) => Arc::new(Transform::rotation(object, Vector3::z(), *angle)),
+                TransformDesc::Rotate { axis, angle } => {
+                    if to_vec3(axis).norm() == 0.0 {
+                        return Err("rotation axis must not be zero".to_string());
+                    }
+                    Arc::new(Transform::rotation(object, to_vec3(axis), *angle))
+                }
+                TransformDesc::Scale(scale) => {
+                    let scale = match scale {
+                        ScaleDesc::Uniform(s) => Vector3::new(*s, *s, *s),
+                        ScaleDesc::Axes(s) => to_vec3(s),
+                    };
+                    Arc::new(Transform::scaling(object, scale).ok_or("scale factors must not be zero")?)
+                }
+                TransformDesc::Matrix(rows) => {
+                    let matrix = Matrix4::from_fn(|i, j| rows[i][j]);
+                    Arc::new(Transform::new(object, matrix).ok_or("transform matrix is not invertible")?)
+                }
+            };
+        }
+        Ok(object)
+    }
+
+    fn environment(&mut self, desc:&EnvironmentDesc) -> Result<Arc<dyn Environment>, String> {
+        match &desc.source {
+            EnvironmentSource::Image { file, intensity, rotation } => {
+                let path = self.dir.join(file);
+                let environment = ImageEnvironment::load(&path, *intensity, *rotation)
+                    .ok_or_else(|| format!("could not load environment map '{}'", path.display()))?;
+                Ok(Arc::new(environment))
+            }
+            EnvironmentSource::Sky {
+                sun_direction:direction, sun_elevation, sun_azimuth, turbidity,
+                intensity, sun_intensity, sun_size, ground,
+            } => {
+                let direction = match direction {
+                    Some(d) if to_vec3(d).norm() == 0.0 => return Err("sun direction must not be zero".to_string()),
+                    Some(d) => to_vec3(d),
+                    None => sun_direction(*sun_elevation, *sun_azimuth),
+                };
+                if *sun_size <= 0.0 {
+                    return Err("'sun_size' must be positive".to_string());
+                }
+                Ok(Arc::new(Sky::new(direction, *turbidity, *intensity, *sun_intensity, *sun_size, to_vec3(ground))))
+            }
+        }
+    }
+
+    fn delta_light(&mut self, desc:&DeltaLightDesc) -> Result<Arc<dyn Light>, String> {
+        match desc {
+            DeltaLightDesc::Point { position, intensity, direction, profile } => {
+                let profile = match profile {
+                    Some(profile) => Some(self.emission_profile(profile)?),
+                    None => None,
+                };
+                let mut light = PointLight::new(to_vec3(position), to_vec3(intensity) * peak_candela(&profile));
+                if let Some(profile) = profile {
+                    if to_vec3(direction).norm() == 0.0 {
+                        return Err("point light direction must not be zero".to_string());
+                    }
+                    light.set_profile(profile.shape, to_vec3(direction));
+                }
+                Ok(Arc::new(light))
+            }
+            DeltaLightDesc::Spot { position, direction, intensity, angle, falloff_start, profile } => {
+                if to_vec3(direction).norm() == 0.0 {
+                    return Err("spot light direction must not be zero".to_string());
+                }
+                if *angle <= 0.0 || *angle > 180.0 {
+                    return Err("spot light 'angle' must be in (0, 180] degrees".to_string());
+                }
+                let falloff_start = falloff_start.unwrap_or(*angle);
+                let profile = match profile {
+                    Some(profile) => Some(self.emission_profile(profile)?),
+                    None => None,
+                };
+                let intensity = to_vec3(intensity) * peak_candela(&profile);
+                let mut light = SpotLight::new(to_vec3(position), to_vec3(direction), intensity, *angle, falloff_start);
+                if let Some(profile) = profile {
+                    light.set_profile(profile.shape);
+                }
+                Ok(Arc::new(light))
+            }
+            DeltaLightDesc::Directional { direction, irradiance } => {
+                if to_vec3(direction).norm() == 0.0 {
+                    return Err("directional light direction must not be zero".to_string());
+                }
+                Ok(Arc::new(DirectionalLight::new(to_vec3(direction), to_vec3(irradiance))))
+            }
+        }
+    }
+
+    fn list(&mut self, entries:&[Spanned<ObjectDesc>], is_light:bool) -> Result<HittableList, String> {
+        let mut list = HittableList::new();
+        for entry in entries {
+            let object = self.object(entry.get_ref(), is_light, Some(1.0)).map_err(|e| self.error(entry.span(), e))?;
+            list.add(object);
+        }
+        Ok(list)
+    }
+}
+
+fn transform_area_scale(transforms:&[TransformDesc]) -> Option<f64> {
+    let mut scale = 1.0;
+    for transform in transforms {
+        match transform {
+            TransformDesc::Scale(ScaleDesc::Uniform(s)) => scale *= s * s,
+            TransformDesc::Scale(ScaleDesc::Axes(_)) | TransformDesc::Matrix(_) => return None,
+            _ => {}
+        }
+    }
+    Some(scale)
+}
+
+fn is_photometric(profile:&Option<EmissionProfileDesc>) -> bool {
+    matches!(profile, Some(EmissionProfileDesc::Ies { photometric:true, .. }))
+}
+
+// A photometric profile turns the light's intensity into a tint on the file's
+// absolute candela values.
+fn peak_candela(profile:&Option<Profile>) -> f64 {
+    profile.as_ref().and_then(|p| p.photometry).map_or(1.0, |(candela, _)| candela)
+}
+
+pub fn parse_scene(filename:&str, source:&str, seed:Option<u64>) -> Result<Scene, String> {
+    let desc:SceneDesc = toml::from_str(source).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut builder = SceneBuilder {
+        filename,
+        source,
+        dir:Path::new(filename).parent().map(Path::to_path_buf).unwrap_or_default(),
+        desc:&desc,
+        textures:HashMap::new(),
+        materials:HashMap::new(),
+        resolving:HashSet::new(),
+        resolving_materials:HashSet::new(),
+        bvh:BvhBuilder::new(),
+    };
+    builder.bvh.split_method = match desc.bvh_split {
+        BvhSplitDesc::Sah => SplitMethod::Sah,
+        BvhSplitDesc::Middle => SplitMethod::Middle,
+    };
+
+    let mut camera = Camera::new();
+    for (key, value) in desc.camera.iter() {
+        set_camera_field(&mut camera, key, value.get_ref()).map_err(|e| builder.error(value.span(), e))?;
+    }
+    if let Some(seed) = seed {
+        camera.seed = seed;
+    }
+    seed_rng(camera.seed, 0);
+
+    let world = builder.list(&desc.objects, false)?;
+    let world:Arc<dyn Hittable> = if desc.bvh && !world.objects.is_empty() {
+        Arc::new(LinearBvh::new(&builder.bvh, &world.objects))
+    } else {
+        Arc::new(world)
+    };
+
+    let mut lights = if desc.auto_lights {
+        HittableList::emitters(&world)
+    } else {
+        HittableList::new()
+    };
+    for object in builder.list(&desc.lights, true)?.objects {
+        lights.add(object);
+    }
+    if let Some(entry) = &desc.environment {
+        let environment = builder.environment(entry.get_ref()).map_err(|e| builder.error(entry.span(), e))?;
+        if entry.get_ref().sample {
+            lights.add(Arc::new(EnvironmentLight::new(environment.clone())));
+        }
+        camera.environment = Some(environment);
+    }
+    for entry in desc.delta_lights.iter() {
+        let light = builder.delta_light(entry.get_ref()).map_err(|e| builder.error(entry.span(), e))?;
+        camera.delta_lights.push(light);
+    }
+
+    let sampling = match desc.light_sampler {
+        LightSamplerDesc::Uniform => LightSampling::Uniform,
+        LightSamplerDesc::Power => LightSampling::Power,
+        LightSamplerDesc::Bvh => LightSampling::Bvh,
+    };
+
+    Ok(Scene {
+        camera,
+        world,
+        lights:Arc::new(LightList::new(&lights, sampling)),
+    })
+}
+
+pub fn load_scene(filename:&str, seed:Option<u64>) -> Result<Scene, String> {
+    let source = fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    parse_scene(filename, &source, seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use std::f64::consts::PI;
+
+    const HEADER:&str = "[camera]\nimage_width = 8\n\n[materials.white]\ntype = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]\n\n";
+
+    fn parse(body:&str) -> Result<Scene, String> {
+        parse_scene("test.toml", &format!("{}{}", HEADER, body), None)
+    }
+
+    #[test]
+    fn accepts_object_options() {
+        let scene = "[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\
+            transform = [{ translate = [1.0, 0.0, 0.0] }]\ncutout = { alpha = 1.0 }\n";
+        assert!(parse(scene).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_object_fields() {
+        let typo = "[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\nmateral = \"white\"\n";
+        assert!(parse(typo).err().unwrap().contains("unknown field `materal`"));
+        let option = "[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\ntransfrom = []\n";
+        assert!(parse(option).err().unwrap().contains("unknown field `transfrom`"));
+        let nested = "[[objects]]\ntype = \"constant_medium\"\ndensity = 0.1\nalbedo = 1.0\n\
+            boundary = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, radus = 2.0 }\n";
+        assert!(parse(nested).err().unwrap().contains("unknown field `radus`"));
+    }
+
+    #[test]
+    fn rejects_unknown_environment_fields() {
+        assert!(parse("[environment]\ntype = \"sky\"\nsample = false\n").is_ok());
+        assert!(parse("[environment]\ntype = \"sky\"\nturbidty = 4.0\n").err().unwrap().contains("unknown field `turbidty`"));
+        assert!(parse("[environment]\ntype = \"sky\"\nsampel = false\n").err().unwrap().contains("unknown field `sampel`"));
+    }
+
+    #[test]
+    fn missing_image_texture_is_an_error() {
+        let scene = "[textures.map]\ntype = \"image\"\nfile = \"missing.png\"\n\n[materials.mapped]\ntype = \"lambertian\"\nalbedo = \"map\"\n\n\
+            [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"mapped\"\n";
+        let error = parse(scene).err().unwrap();
+        assert!(error.contains("could not load image 'missing.png'"), "{}", error);
+        assert!(error.starts_with("test.toml:"), "{}", error);
+    }
+
+    const POWER_LIGHT:&str = "[materials.lamp]\ntype = \"diffuse_light\"\nemit = [1.0, 1.0, 1.0]\npower = 100.0\n\n";
+
+    #[test]
+    fn power_applies_to_direct_object_use() {
+        let scene = format!("{}[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [0.0, 1.0, 0.0]\nmaterial = \"lamp\"\n", POWER_LIGHT);
+        assert!(parse(&scene).is_ok());
+    }
+
+    #[test]
+    fn principled_ior_accepts_a_texture() {
+        for ior in ["1.33", "\"varying\""] {
+            let scene = format!("[textures.varying]\ntype = \"noise\"\n\n\
+                [materials.glass]\ntype = \"principled\"\nbase_color = [1.0, 1.0, 1.0]\ntransmission = 1.0\nior = {}\n", ior);
+            assert!(parse(&scene).is_ok(), "{}", ior);
+        }
+    }
+
+    #[test]
+    fn mesh_without_material_is_an_error() {
+        let error = parse("[[objects]]\ntype = \"mesh\"\nfile = \"bunny.obj\"\n").err().unwrap();
+        assert!(error.contains("object has no material"), "{}", error);
+    }
+
+    #[test]
+    fn camera_fields_are_range_checked() {
+        for (field, value) in [("samples_per_pixel", "0"), ("image_width", "-4"), ("seed", "-1"), ("threads", "0")] {
+            let source = format!("[camera]\n{} = {}\n", field, value);
+            let error = parse_scene("test.toml", &source, None).err().unwrap();
+            assert!(error.starts_with("test.toml:2:"), "{}", error);
+            assert!(error.contains(&format!("camera field '{}' must be at least", field)), "{}", error);
+        }
+        assert!(parse_scene("test.toml", "[camera]\nsamples_per_pixel = 1\nseed = 0\n", None).is_ok());
+    }
+
+    #[test]
+    fn bvh_split_selects_the_build_method() {
+        let objects = "[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n\n\
+            [[objects]]\ntype = \"sphere\"\ncenter = [3.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"white\"\n";
+        for split in ["sah", "middle"] {
+            let source = format!("bvh = true\nbvh_split = \"{}\"\n{}{}", split, HEADER, objects);
+            assert!(parse_scene("test.toml", &source, None).is_ok(), "{}", split);
+        }
+        let source = format!("bvh = true\nbvh_split = \"octree\"\n{}{}", HEADER, objects);
+        assert!(parse_scene("test.toml", &source, None).is_err());
+    }
+
+    // Integrates luminance times cosine over the hemisphere above a 2x1 quad
+    // whose emission is the same everywhere.
+    fn quad_flux(material:&str) -> f64 {
+        let scene = format!("{}[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [2.0, 0.0, 0.0]\nv = [0.0, 1.0, 0.0]\nmaterial = \"lamp\"\n", material);
+        let scene = parse(&scene).unwrap();
+        let center = Vector3::new(1.0, 0.5, 0.0);
+        let n = 200;
+        let mut flux = 0.0;
+        for j in 0..n {
+            let theta = PI / 2.0 * (j as f64 + 0.5) / n as f64;
+            for i in 0..n {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / n as f64;
+                let w = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                let r = Ray::new(center + 0.1 * w, -w);
+                let mut rec = HitRecord::new();
+                assert!(scene.world.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec));
+                let radiance = rec.mat.emitted(&r, &rec, rec.u, rec.v, &rec.p);
+                flux += luminance(&radiance) * theta.cos() * theta.sin();
+            }
+        }
+        flux * (PI / 2.0 / n as f64) * (2.0 * PI / n as f64) * 2.0
+    }
+
+    #[test]
+    fn power_sets_emitted_flux_whatever_the_emit_color() {
+        for emit in ["[15.0, 15.0, 15.0]", "[1.0, 0.5, 0.25]", "\"warm\""] {
+            let material = format!("[textures.warm]\ntype = \"solid\"\ncolor = [4.0, 2.0, 1.0]\n\n\
+                [materials.lamp]\ntype = \"diffuse_light\"\nemit = {}\npower = 100.0\n\n", emit);
+            let flux = quad_flux(&material);
+            assert!((flux - 100.0).abs() < 0.1, "emit {} gives flux {}", emit, flux);
+        }
+        let shaped = "[materials.lamp]\ntype = \"diffuse_light\"\nemit = [15.0, 15.0, 15.0]\npower = 100.0\n\
+            profile = { type = \"cosine_power\", exponent = 4.0 }\n\n";
+        assert!((quad_flux(shaped) - 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn power_needs_a_constant_emit_color() {
+        let scene = "[textures.marble]\ntype = \"noise\"\n\n[materials.lamp]\ntype = \"diffuse_light\"\nemit = \"marble\"\npower = 100.0\n\n\
+            [[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [0.0, 1.0, 0.0]\nmaterial = \"lamp\"\n";
+        let error = parse(scene).err().unwrap();
+        assert!(error.contains("needs a constant, non-black 'emit' color"), "{}", error);
+    }
+
+    #[test]
+    fn power_through_a_wrapping_material_is_an_error() {
+        let scene = format!("{}[materials.bumpy]\ntype = \"bump\"\nmaterial = \"lamp\"\nheight = 0.5\n\n\
+            [[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [0.0, 1.0, 0.0]\nmaterial = \"bumpy\"\n", POWER_LIGHT);
+        let error = parse(&scene).err().unwrap();
+        assert!(error.contains("material 'lamp' gives 'power'"), "{}", error);
+    }
+
+    fn point_light_radiance(profile:&str) -> Result<Vector3<f64>, String> {
+        let body = format!("[[delta_lights]]\ntype = \"point\"\nposition = [0.0, 0.0, 0.0]\nintensity = [1.0, 0.5, 0.25]\n\
+            profile = {{ type = \"ies\", {} }}\n", profile);
+        let scene = parse_scene("scenes/test.toml", &format!("{}{}", HEADER, body), None)?;
+        Ok(scene.camera.delta_lights[0].sample_li(&Vector3::new(0.0, -2.0, 0.0)).unwrap().radiance)
+    }
+
+    #[test]
+    fn photometric_profile_uses_absolute_candela() {
+        let relative = point_light_radiance("file = \"ies/isotropic.ies\"").unwrap();
+        assert!((relative - Vector3::new(0.25, 0.125, 0.0625)).norm() < 1e-9);
+        let absolute = point_light_radiance("file = \"ies/isotropic.ies\", photometric = true").unwrap();
+        assert!((absolute - Vector3::new(25.0, 12.5, 6.25)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn lumens_rescale_relative_photometry() {
+        let rated = point_light_radiance("file = \"ies/downlight.ies\", photometric = true").unwrap();
+        let doubled = point_light_radiance("file = \"ies/downlight.ies\", photometric = true, lumens = 2000.0").unwrap();
+        assert!(rated.x > 0.0);
+        assert!((doubled - 2.0 * rated).norm() < 1e-9);
+        let error = point_light_radiance("file = \"ies/isotropic.ies\", photometric = true, lumens = 2000.0").err().unwrap();
+        assert!(error.contains("uses absolute photometry"), "{}", error);
+        let error = point_light_radiance("file = \"ies/downlight.ies\", lumens = 2000.0").err().unwrap();
+        assert!(error.contains("without 'photometric = true'"), "{}", error);
+    }
+
+    #[test]
+    fn photometric_area_light_needs_direct_object_use() {
+        let lamp = "[materials.lamp]\ntype = \"diffuse_light\"\nemit = [1.0, 1.0, 1.0]\n\
+            profile = { type = \"ies\", file = \"ies/downlight.ies\", photometric = true }\n\n";
+        let quad = "[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [0.0, 1.0, 0.0]\nmaterial = ";
+        let direct = format!("{}{}{}\"lamp\"\n", HEADER, lamp, quad);
+        assert!(parse_scene("scenes/test.toml", &direct, None).is_ok());
+        let wrapped = format!("{}{}[materials.bumpy]\ntype = \"bump\"\nmaterial = \"lamp\"\nheight = 0.5\n\n{}\"bumpy\"\n", HEADER, lamp, quad);
+        let error = parse_scene("scenes/test.toml", &wrapped, None).err().unwrap();
+        assert!(error.contains("material 'lamp' uses a photometric profile"), "{}", error);
+    }
+
+    fn render(source:&str, seed:Option<u64>, name:&str) -> Framebuffer {
+        let mut scene = parse_scene("test.toml", source, seed).unwrap();
+        scene.camera.output_file = std::env::temp_dir().join(format!("light-seed-{}-{}.ppm", std::process::id(), name)).to_string_lossy().into_owned();
+        let framebuffer = scene.camera.render(&scene.world, &scene.lights).unwrap();
+        let _ = fs::remove_file(&scene.camera.output_file);
+        framebuffer
+    }
+
+    fn noise_scene(seed:u64) -> String {
+        format!("[camera]\nimage_width = 16\nsamples_per_pixel = 4\nmax_depth = 4\nthreads = 4\nseed = {}\n\
+            lookfrom = [0.0, 0.0, 5.0]\nlookat = [0.0, 0.0, 0.0]\nbackground = [1.0, 1.0, 1.0]\n\n\
+            [textures.marble]\ntype = \"noise\"\nscale = 4.0\n\n\
+            [materials.marble]\ntype = \"lambertian\"\nalbedo = \"marble\"\n\n\
+            [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.5\nmaterial = \"marble\"\n", seed)
+    }
+
+    #[test]
+    fn same_seed_renders_identical_framebuffers() {
+        let a = render(&noise_scene(11), None, "a");
+        let b = render(&noise_scene(11), None, "b");
+        assert!(a.pixels == b.pixels);
+    }
+
+    #[test]
+    fn scene_seed_drives_scene_construction() {
+        let a = render(&noise_scene(1), None, "c");
+        let b = render(&noise_scene(2), None, "d");
+        assert!(a.pixels != b.pixels);
+        let c = render(&noise_scene(1), Some(9), "e");
+        let d = render(&noise_scene(2), Some(9), "f");
+        assert!(c.pixels == d.pixels);
+    }
+}
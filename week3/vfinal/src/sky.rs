@@ -0,0 +1,179 @@
+use nalgebra::Vector3;
+use std::f64::consts::PI;
+
+use crate::distribution::Distribution2D;
+use crate::environment::{direction_to_uv, uv_to_direction, Environment};
+use crate::onb::Onb;
+use crate::rtweekend::random_double;
+use crate::spectrum::xyz_to_linear_srgb;
+
+const LUMINANCE_SCALE:f64 = 0.05;
+const SUN_LUMINANCE:f64 = 1.6e6;
+const TABLE_WIDTH:usize = 128;
+const TABLE_HEIGHT:usize = 64;
+
+pub struct Sky {
+    sun_direction:Vector3<f64>,
+    sun_radiance:Vector3<f64>,
+    cos_sun_max:f64,
+    theta_s:f64,
+    zenith:Vector3<f64>,
+    perez:[[f64;5];3],
+    ground:Vector3<f64>,
+    intensity:f64,
+    sun_weight:f64,
+    distribution:Distribution2D,
+}
+
+fn luminance(c:&Vector3<f64>) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn perez(c:&[f64;5], cos_theta:f64, gamma:f64, cos_gamma:f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta.max(1e-3)).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+fn zenith(turbidity:f64, theta_s:f64) -> Vector3<f64> {
+    let t = turbidity;
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let y_z = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let (s, s2, s3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+    let x_z = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+        + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+        + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+    let y_z2 = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+        + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+        + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+    Vector3::new(y_z.max(0.0), x_z, y_z2)
+}
+
+fn perez_coefficients(turbidity:f64) -> [[f64;5];3] {
+    let t = turbidity;
+    [
+        [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+        [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+        [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+    ]
+}
+
+fn sun_transmittance(turbidity:f64, theta_s:f64) -> Vector3<f64> {
+    let m = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let tau = |lambda:f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * m).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * m).exp();
+        rayleigh * aerosol
+    };
+    Vector3::new(tau(0.680), tau(0.550), tau(0.440))
+}
+
+pub fn sun_direction(elevation:f64, azimuth:f64) -> Vector3<f64> {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Vector3::new(elevation.cos() * azimuth.cos(), elevation.sin(), elevation.cos() * azimuth.sin())
+}
+
+impl Sky {
+    pub fn new (sun_direction:Vector3<f64>, turbidity:f64, intensity:f64, sun_intensity:f64, sun_size:f64, ground:Vector3<f64>) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let turbidity = turbidity.clamp(1.7, 10.0);
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+        let cos_sun_max = (0.5 * sun_size).to_radians().cos();
+        let sun_radiance = if sun_direction.y > 0.0 {
+            SUN_LUMINANCE * LUMINANCE_SCALE * sun_intensity * sun_transmittance(turbidity, theta_s)
+        } else {
+            Vector3::zeros()
+        };
+
+        let mut sky = Sky {
+            sun_direction,
+            sun_radiance,
+            cos_sun_max,
+            theta_s,
+            zenith:zenith(turbidity, theta_s),
+            perez:perez_coefficients(turbidity),
+            ground,
+            intensity,
+            sun_weight:0.0,
+            distribution:Distribution2D::new(&[1.0], 1, 1),
+        };
+
+        let mut func = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        for j in 0..TABLE_HEIGHT {
+            let v = (j as f64 + 0.5) / TABLE_HEIGHT as f64;
+            for i in 0..TABLE_WIDTH {
+                let u = (i as f64 + 0.5) / TABLE_WIDTH as f64;
+                func.push(luminance(&sky.sky_radiance(&uv_to_direction(u, v))) * (v * PI).sin());
+            }
+        }
+        let sky_power = 2.0 * PI * PI * func.iter().sum::<f64>() / func.len() as f64;
+        let sun_power = luminance(&sky.sun_radiance) * 2.0 * PI * (1.0 - cos_sun_max);
+        if sun_power + sky_power > 0.0 {
+            sky.sun_weight = sun_power / (sun_power + sky_power);
+        }
+        sky.distribution = Distribution2D::new(&func, TABLE_WIDTH, TABLE_HEIGHT);
+        sky
+    }
+
+    fn sky_radiance(&self, d:&Vector3<f64>) -> Vector3<f64> {
+        if d.y <= 0.0 {
+            return self.intensity * self.ground;
+        }
+        let cos_theta = d.y;
+        let cos_gamma = d.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let cos_theta_s = self.theta_s.cos();
+        let mut yxy = Vector3::zeros();
+        for k in 0..3 {
+            let ratio = perez(&self.perez[k], cos_theta, gamma, cos_gamma) / perez(&self.perez[k], 1.0, self.theta_s, cos_theta_s);
+            yxy[k] = self.zenith[k] * ratio;
+        }
+        let (big_y, x, y) = (yxy.x * LUMINANCE_SCALE, yxy.y, yxy.z);
+        if y <= 0.0 {
+            return Vector3::zeros();
+        }
+        let xyz = Vector3::new(x / y * big_y, big_y, (1.0 - x - y) / y * big_y);
+        self.intensity * xyz_to_linear_srgb(&xyz).map(|c| c.max(0.0))
+    }
+
+    fn in_sun(&self, d:&Vector3<f64>) -> bool {
+        d.dot(&self.sun_direction) >= self.cos_sun_max
+    }
+
+    fn sun_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_max))
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction:&Vector3<f64>) -> Vector3<f64> {
+        let d = direction.normalize();
+        let sky = self.sky_radiance(&d);
+        if self.in_sun(&d) {sky + self.sun_radiance} else {sky}
+    }
+
+    fn pdf_value(&self, direction:&Vector3<f64>) -> f64 {
+        if direction.norm_squared() == 0.0 {
+            return 0.0;
+        }
+        let d = direction.normalize();
+        let (u, v) = direction_to_uv(&d);
+        let sin_theta = (v * PI).sin();
+        let sky_pdf = if sin_theta > 0.0 {self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)} else {0.0};
+        let sun_pdf = if self.in_sun(&d) {self.sun_pdf()} else {0.0};
+        self.sun_weight * sun_pdf + (1.0 - self.sun_weight) * sky_pdf
+    }
+
+    fn random(&self) -> Vector3<f64> {
+        if random_double() < self.sun_weight {
+            let mut uvw = Onb::new();
+            uvw.build_from_w(&self.sun_direction);
+            let cos_theta = 1.0 - random_double() * (1.0 - self.cos_sun_max);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * random_double();
+            uvw.local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+        } else {
+            let (u, v, _) = self.distribution.sample_continuous(random_double(), random_double());
+            uv_to_direction(u, v)
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use nalgebra::Vector3;
+use std::sync::OnceLock;
+
+pub const LAMBDA_MIN:f64 = 380.0;
+pub const LAMBDA_MAX:f64 = 720.0;
+
+const SMITS_BINS:usize = 10;
+const SMITS_WHITE:[f64;SMITS_BINS] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN:[f64;SMITS_BINS] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA:[f64;SMITS_BINS] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW:[f64;SMITS_BINS] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED:[f64;SMITS_BINS] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN:[f64;SMITS_BINS] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE:[f64;SMITS_BINS] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+pub fn sample_wavelength(u:f64) -> f64 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+pub fn wavelength_pdf() -> f64 {
+    1.0 / (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+pub fn wavelengths(hero:f64) -> Vector3<f64> {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let rotate = |i:f64| LAMBDA_MIN + (hero - LAMBDA_MIN + i * range / 3.0).rem_euclid(range);
+    Vector3::new(hero, rotate(1.0), rotate(2.0))
+}
+
+fn smits_basis(table:&[f64;SMITS_BINS], lambda:f64) -> f64 {
+    let x = ((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN)).clamp(0.0, 1.0) * (SMITS_BINS - 1) as f64;
+    let i = (x as usize).min(SMITS_BINS - 2);
+    let t = x - i as f64;
+    (1.0 - t) * table[i] + t * table[i + 1]
+}
+
+fn rgb_to_spectrum_at(rgb:&Vector3<f64>, lambda:f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let basis = |table| smits_basis(table, lambda);
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE) + if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        }
+    }
+    else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE) + if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        }
+    }
+    else {
+        b * basis(&SMITS_WHITE) + if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        }
+    }
+}
+
+pub fn rgb_to_spectrum(rgb:&Vector3<f64>, lambdas:&Vector3<f64>) -> Vector3<f64> {
+    lambdas.map(|lambda| rgb_to_spectrum_at(rgb, lambda))
+}
+
+fn lobe(lambda:f64, mu:f64, sigma_low:f64, sigma_high:f64) -> f64 {
+    let t = (lambda - mu) / if lambda < mu {sigma_low} else {sigma_high};
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_xyz(lambda:f64) -> Vector3<f64> {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Vector3::new(x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(xyz:&Vector3<f64>) -> Vector3<f64> {
+    let r = 3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z;
+    let g = -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z;
+    let b = 0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z;
+    Vector3::new(r, g, b)
+}
+
+fn equal_energy_white() -> &'static (f64, Vector3<f64>) {
+    static WHITE:OnceLock<(f64, Vector3<f64>)> = OnceLock::new();
+    WHITE.get_or_init(|| {
+        let steps = 3400;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Vector3::zeros();
+        for i in 0..steps {
+            xyz += cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step) * step;
+        }
+        let y_integral = xyz.y;
+        (y_integral, xyz_to_linear_srgb(&(xyz / y_integral)))
+    })
+}
+
+pub fn spectrum_to_rgb(values:&Vector3<f64>, lambdas:&Vector3<f64>) -> Vector3<f64> {
+    let (y_integral, white) = equal_energy_white();
+    let mut xyz = Vector3::zeros();
+    for i in 0..3 {
+        xyz += cie_xyz(lambdas[i]) * values[i];
+    }
+    xyz /= 3.0 * wavelength_pdf() * y_integral;
+    xyz_to_linear_srgb(&xyz).component_div(white)
+}
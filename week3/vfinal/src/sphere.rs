@@ -8,6 +8,7 @@ use crate::interval::Interval;
 use crate::material::Material;
 use crate::aabb::Aabb;
 use crate::rtweekend::random_double;
+use crate::light_sampler::{emitted_power, LightBounds};
 
 pub struct Sphere {
     pub center1:Vector3<f64>,
@@ -52,6 +53,16 @@ impl Sphere {
         *u = phi / (2.0 * std::f64::consts::PI);
         *v = theta / std::f64::consts::PI;
     }
+    pub fn get_sphere_tangents(p:&Vector3<f64>, radius:f64) -> (Vector3<f64>, Vector3<f64>) {
+        let pi = std::f64::consts::PI;
+        let dpdu = 2.0 * pi * radius * Vector3::new(p.z, 0.0, -p.x);
+        let sin_theta = (1.0 - p.y * p.y).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            return (Vector3::new(2.0 * pi * radius, 0.0, 0.0), Vector3::new(0.0, 0.0, pi * radius));
+        }
+        let dpdv = pi * radius * Vector3::new(-p.y * p.x / sin_theta, sin_theta, -p.y * p.z / sin_theta);
+        (dpdu, dpdv)
+    }
     pub fn random_to_sphere(radius:f64, distance_squared:f64) -> Vector3<f64> {
         let r1 = random_double();
         let r2 = random_double();
@@ -92,6 +103,7 @@ impl Hittable for Sphere {
         let outward_normal = (rec.p - center)/self.radius;
         rec.set_face_normal(&r, &outward_normal);
         Self::get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+        (rec.dpdu, rec.dpdv) = Self::get_sphere_tangents(&outward_normal, self.radius);
         rec.mat = self.mat.clone();
         true
     }
@@ -118,4 +130,18 @@ impl Hittable for Sphere {
         uvw.build_from_w(&direction);
         uvw.local_vec(&Self::random_to_sphere(self.radius, distance_squared))
     }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let normal = Vector3::new(0.0,1.0,0.0);
+        let p = self.center1 + self.radius * normal;
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        let phi = emitted_power(&self.mat, &p, &normal, 0.5, 1.0, area);
+        Some(LightBounds::new(self.bbox.clone(), phi, normal, -1.0, 0.0, false))
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        if self.mat.is_emissive() {
+            emitters.push(self);
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use nalgebra::Vector3;
+use std::path::Path;
 use std::sync::Arc;
 use crate::color;
 use crate::rtw_stb_image::RtwImage;
@@ -7,6 +8,11 @@ use crate::perlin::Perlin;
 
 pub trait Texture: Send + Sync {
     fn value (&self, u:f64, v:f64, p:&Vector3<f64>) -> Vector3<f64>;
+
+    fn alpha (&self, u:f64, v:f64, p:&Vector3<f64>) -> f64 {
+        let c = self.value(u, v, p);
+        (c.x + c.y + c.z) / 3.0
+    }
 }
 
 pub struct SolidColor {
@@ -77,6 +83,12 @@ impl ImageTexture {
             image:RtwImage::new(filename),
         }
     }
+
+    pub fn from_path (path:&Path) -> Option<Self> {
+        Some(ImageTexture {
+            image:RtwImage::load(path)?,
+        })
+    }
 }
 
 impl Texture for ImageTexture {
@@ -92,6 +104,23 @@ impl Texture for ImageTexture {
         let color_scale = 1.0 / 255.0;
         Vector3::new(color_scale * pixel[0] as f64, color_scale * pixel[1] as f64, color_scale * pixel[2] as f64)
     }
+
+    fn alpha (&self, u:f64, v:f64, p:&Vector3<f64>) -> f64 {
+        if self.image.height() <= 0 {
+            return 1.0;
+        }
+        let u = Interval::new(0.0,1.0).clamp(u);
+        let v = 1.0 - Interval::new(0.0,1.0).clamp(v);
+        let i = (u * self.image.width() as f64) as i64;
+        let j = (v * self.image.height() as f64) as i64;
+        match self.image.alpha_data(i, j) {
+            Some(alpha) => alpha as f64 / 255.0,
+            None => {
+                let pixel = self.image.pixel_data(i, j);
+                (pixel[0] + pixel[1] + pixel[2]) as f64 / (3.0 * 255.0)
+            }
+        }
+    }
 }
 
 pub struct NoiseTexture {
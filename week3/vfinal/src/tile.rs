@@ -0,0 +1,38 @@
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub x0:i64,
+    pub y0:i64,
+    pub x1:i64,
+    pub y1:i64,
+}
+
+impl Tile {
+    pub fn new(x0:i64, y0:i64, x1:i64, y1:i64) -> Self {
+        Tile { x0, y0, x1, y1 }
+    }
+
+    pub fn width(&self) -> i64 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> i64 {
+        self.y1 - self.y0
+    }
+
+    pub fn split(image_width:i64, image_height:i64, tile_size:i64) -> Vec<Tile> {
+        let tile_size = tile_size.max(1);
+        let mut tiles = Vec::new();
+        let mut y0 = 0;
+        while y0 < image_height {
+            let y1 = (y0 + tile_size).min(image_height);
+            let mut x0 = 0;
+            while x0 < image_width {
+                let x1 = (x0 + tile_size).min(image_width);
+                tiles.push(Tile::new(x0, y0, x1, y1));
+                x0 = x1;
+            }
+            y0 = y1;
+        }
+        tiles
+    }
+}
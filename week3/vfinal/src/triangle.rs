@@ -0,0 +1,404 @@
+use nalgebra::{Vector2, Vector3};
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::LinearBvh;
+use crate::distribution::Distribution1D;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::random_double;
+use crate::light_sampler::{emitted_power, LightBounds};
+
+pub struct MeshData {
+    pub positions:Vec<Vector3<f64>>,
+    pub normals:Vec<Vector3<f64>>,
+    pub uvs:Vec<Vector2<f64>>,
+    pub position_indices:Vec<[usize;3]>,
+    pub normal_indices:Vec<Option<[usize;3]>>,
+    pub uv_indices:Vec<Option<[usize;3]>>,
+    pub material_indices:Vec<usize>,
+    pub materials:Vec<Arc<dyn Material>>,
+}
+
+impl MeshData {
+    pub fn new() -> Self {
+        MeshData {
+            positions:Vec::new(),
+            normals:Vec::new(),
+            uvs:Vec::new(),
+            position_indices:Vec::new(),
+            normal_indices:Vec::new(),
+            uv_indices:Vec::new(),
+            material_indices:Vec::new(),
+            materials:Vec::new(),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.position_indices.len()
+    }
+
+    pub fn add_triangle(&mut self, position:[usize;3], normal:Option<[usize;3]>, uv:Option<[usize;3]>, material:usize) {
+        self.position_indices.push(position);
+        self.normal_indices.push(normal);
+        self.uv_indices.push(uv);
+        self.material_indices.push(material);
+    }
+
+    pub fn vertices(&self, index:usize) -> [Vector3<f64>;3] {
+        let [a, b, c] = self.position_indices[index];
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+}
+
+impl Default for MeshData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    mesh:Arc<MeshData>,
+    index:usize,
+    bbox:Aabb,
+    area:f64,
+}
+
+impl Triangle {
+    pub fn new(a:Vector3<f64>, b:Vector3<f64>, c:Vector3<f64>, mat:Arc<dyn Material>) -> Self {
+        let mut mesh = MeshData::new();
+        mesh.positions = vec![a, b, c];
+        mesh.materials.push(mat);
+        mesh.add_triangle([0, 1, 2], None, None, 0);
+        Self::from_mesh(Arc::new(mesh), 0)
+    }
+
+    pub fn from_mesh(mesh:Arc<MeshData>, index:usize) -> Self {
+        let [p0, p1, p2] = mesh.vertices(index);
+        let bbox = Aabb::merge(&Aabb::initial(&p0, &p1), &Aabb::initial(&p2, &p2));
+        let area = 0.5 * (p1 - p0).cross(&(p2 - p0)).norm();
+        Triangle {
+            mesh,
+            index,
+            bbox,
+            area,
+        }
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mesh.materials[self.mesh.material_indices[self.index]]
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    fn max_dimension(v:&Vector3<f64>) -> usize {
+        if v.x > v.y {
+            if v.x > v.z {0} else {2}
+        }
+        else if v.y > v.z {1} else {2}
+    }
+
+    fn permute(v:&Vector3<f64>, kx:usize, ky:usize, kz:usize) -> Vector3<f64> {
+        Vector3::new(v[kx], v[ky], v[kz])
+    }
+
+    fn uv_tangents(p:&[Vector3<f64>;3], uv:&[Vector2<f64>;3]) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        let duv02 = uv[0] - uv[2];
+        let duv12 = uv[1] - uv[2];
+        let dp02 = p[0] - p[2];
+        let dp12 = p[1] - p[2];
+        let determinant = duv02.x * duv12.y - duv02.y * duv12.x;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / determinant;
+        let dpdu = (duv12.y * dp02 - duv02.y * dp12) * inv_det;
+        let dpdv = (duv02.x * dp12 - duv12.x * dp02) * inv_det;
+        if dpdu.cross(&dpdv).norm_squared() == 0.0 {
+            return None;
+        }
+        Some((dpdu, dpdv))
+    }
+
+    pub fn intersect(&self, r:&Ray, ray_t:&Interval) -> Option<(f64, f64, f64, f64)> {
+        let [p0, p1, p2] = self.mesh.vertices(self.index);
+        let dir = r.direction();
+        let orig = r.origin();
+
+        let kz = Self::max_dimension(&dir.abs());
+        let kx = (kz + 1) % 3;
+        let ky = (kx + 1) % 3;
+        let d = Self::permute(&dir, kx, ky, kz);
+        let mut p0t = Self::permute(&(p0 - orig), kx, ky, kz);
+        let mut p1t = Self::permute(&(p1 - orig), kx, ky, kz);
+        let mut p2t = Self::permute(&(p2 - orig), kx, ky, kz);
+
+        let sx = -d.x / d.z;
+        let sy = -d.y / d.z;
+        let sz = 1.0 / d.z;
+        p0t.x += sx * p0t.z;
+        p0t.y += sy * p0t.z;
+        p1t.x += sx * p1t.z;
+        p1t.y += sy * p1t.z;
+        p2t.x += sx * p2t.z;
+        p2t.y += sy * p2t.z;
+
+        let e0 = p1t.x * p2t.y - p1t.y * p2t.x;
+        let e1 = p2t.x * p0t.y - p2t.y * p0t.x;
+        let e2 = p0t.x * p1t.y - p0t.y * p1t.x;
+        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+            return None;
+        }
+        let det = e0 + e1 + e2;
+        if det == 0.0 {
+            return None;
+        }
+
+        p0t.z *= sz;
+        p1t.z *= sz;
+        p2t.z *= sz;
+        let t_scaled = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
+        let t = t_scaled / det;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        Some((t, e0 * inv_det, e1 * inv_det, e2 * inv_det))
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        let (t, b0, b1, b2) = match self.intersect(r, ray_t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let mesh = &self.mesh;
+        let [p0, p1, p2] = mesh.vertices(self.index);
+
+        let mut geometric_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        let shading_normal = mesh.normal_indices[self.index].map(|[a, b, c]| {
+            (b0 * mesh.normals[a] + b1 * mesh.normals[b] + b2 * mesh.normals[c]).normalize()
+        });
+        if let Some(ns) = shading_normal {
+            if geometric_normal.dot(&ns) < 0.0 {
+                geometric_normal = -geometric_normal;
+            }
+        }
+
+        let (dpdu, dpdv) = match mesh.uv_indices[self.index] {
+            Some([a, b, c]) => {
+                let uv = b0 * mesh.uvs[a] + b1 * mesh.uvs[b] + b2 * mesh.uvs[c];
+                rec.u = uv.x;
+                rec.v = uv.y;
+                Self::uv_tangents(&[p0, p1, p2], &[mesh.uvs[a], mesh.uvs[b], mesh.uvs[c]])
+            }
+            None => {
+                rec.u = b1;
+                rec.v = b2;
+                None
+            }
+        }
+        .unwrap_or((p1 - p0, p2 - p0));
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+
+        rec.t = t;
+        rec.p = b0 * p0 + b1 * p1 + b2 * p2;
+        rec.set_face_normal(r, &geometric_normal);
+        if let Some(ns) = shading_normal {
+            rec.normal = if rec.front_face {ns} else {-ns};
+        }
+        rec.mat = self.material().clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        let r = Ray::new(*origin, *direction);
+        let (t, _, _, _) = match self.intersect(&r, &Interval::new(0.001, f64::INFINITY)) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let [p0, p1, p2] = self.mesh.vertices(self.index);
+        let normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+
+        let distance_squared = t * t * direction.dot(direction);
+        let cosine = direction.dot(&normal).abs() / direction.norm();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        let [p0, p1, p2] = self.mesh.vertices(self.index);
+        let su0 = random_double().sqrt();
+        let b0 = 1.0 - su0;
+        let b1 = random_double() * su0;
+        let p = b0 * p0 + b1 * p1 + (1.0 - b0 - b1) * p2;
+        p - origin
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        let [p0, p1, p2] = self.mesh.vertices(self.index);
+        let normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        let center = (p0 + p1 + p2) / 3.0;
+        let phi = emitted_power(self.material(), &center, &normal, 1.0 / 3.0, 1.0 / 3.0, self.area);
+        Some(LightBounds::new(self.bbox.clone(), phi, normal, 1.0, 0.0, self.material().is_two_sided()))
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        if self.material().is_emissive() {
+            emitters.push(self);
+        }
+    }
+}
+
+pub struct TriangleMesh {
+    triangles:Vec<Arc<Triangle>>,
+    bvh:Arc<dyn Hittable>,
+    distribution:Distribution1D,
+    area:f64,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh:MeshData) -> Self {
+        let mesh = Arc::new(mesh);
+        let triangles:Vec<Arc<Triangle>> = (0..mesh.triangle_count())
+            .map(|index| Arc::new(Triangle::from_mesh(mesh.clone(), index)))
+            .collect();
+        let mut list = HittableList::new();
+        for triangle in triangles.iter() {
+            list.add(triangle.clone());
+        }
+        let bvh:Arc<dyn Hittable> = if list.objects.is_empty() {
+            Arc::new(list)
+        } else {
+            Arc::new(LinearBvh::initial(&mut list))
+        };
+        let areas:Vec<f64> = triangles.iter().map(|triangle| triangle.area()).collect();
+        TriangleMesh {
+            area:areas.iter().sum(),
+            distribution:Distribution1D::new(areas),
+            triangles,
+            bvh,
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit (&self, r:&Ray, ray_t:&Interval, rec:&mut HitRecord) -> bool {
+        self.bvh.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    // Triangles are picked in proportion to their area, so the solid angle
+    // density sums the contribution of every triangle along the direction.
+    fn pdf_value(&self, origin:&Vector3<f64>, direction:&Vector3<f64>) -> f64 {
+        if self.area <= 0.0 {
+            return 0.0;
+        }
+        let r = Ray::new(*origin, *direction);
+        let mut rec = HitRecord::new();
+        let mut t_min = 0.001;
+        let mut pdf = 0.0;
+        while self.bvh.hit(&r, &Interval::new(t_min, f64::INFINITY), &mut rec) {
+            let normal = rec.dpdu.cross(&rec.dpdv).normalize();
+            let distance_squared = rec.t * rec.t * direction.dot(direction);
+            let cosine = direction.dot(&normal).abs() / direction.norm();
+            pdf += distance_squared / (cosine * self.area);
+            t_min = rec.t + 0.0001;
+        }
+        pdf
+    }
+
+    fn random (&self, origin:&Vector3<f64>) -> Vector3<f64> {
+        if self.triangles.is_empty() {
+            return Vector3::new(1.0,0.0,0.0);
+        }
+        let (index, _) = self.distribution.sample_discrete(random_double());
+        self.triangles[index].random(origin)
+    }
+
+    fn collect_emitters(self:Arc<Self>, emitters:&mut Vec<Arc<dyn Hittable>>) {
+        self.bvh.clone().collect_emitters(emitters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::rtweekend::seed_rng;
+    use crate::vec3::random_unit_vector;
+
+    fn white() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn square(mesh:&mut MeshData, z:f64, size:f64) {
+        let base = mesh.positions.len();
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            mesh.positions.push(Vector3::new(x * size, y * size, z));
+        }
+        mesh.add_triangle([base, base + 1, base + 2], None, None, 0);
+        mesh.add_triangle([base, base + 2, base + 3], None, None, 0);
+    }
+
+    fn mesh_of(squares:&[(f64, f64)]) -> TriangleMesh {
+        let mut mesh = MeshData::new();
+        mesh.materials.push(white());
+        for &(z, size) in squares {
+            square(&mut mesh, z, size);
+        }
+        TriangleMesh::new(mesh)
+    }
+
+    #[test]
+    fn mesh_pdf_matches_quad_pdf() {
+        let mesh = mesh_of(&[(2.0, 1.0)]);
+        let quad = Quad::new(Vector3::new(-1.0, -1.0, 2.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0), white());
+        let origin = Vector3::new(0.3, -0.2, -1.0);
+        for direction in [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.2, 0.25, 1.0), Vector3::new(2.0, 0.0, 1.0)] {
+            let expected = quad.pdf_value(&origin, &direction);
+            assert!((mesh.pdf_value(&origin, &direction) - expected).abs() <= 1e-9 * expected.max(1.0));
+        }
+    }
+
+    #[test]
+    fn mesh_samples_hit_the_mesh() {
+        seed_rng(3, 0);
+        let mesh = mesh_of(&[(2.0, 1.0), (4.0, 3.0)]);
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        for _ in 0..1000 {
+            let direction = mesh.random(&origin);
+            assert!(mesh.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+
+    #[test]
+    fn mesh_pdf_integrates_to_one_over_overlapping_layers() {
+        seed_rng(5, 0);
+        let mesh = mesh_of(&[(1.0, 1.0), (2.0, 1.5)]);
+        let origin = Vector3::new(0.1, 0.2, 0.0);
+        let samples = 400_000;
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += mesh.pdf_value(&origin, &random_unit_vector());
+        }
+        let integral = 4.0 * std::f64::consts::PI * sum / samples as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+    }
+}